@@ -3,28 +3,9 @@
 Channel.fromFilePairs(params.input_reads)
     .set{reads}
 
-process unzip {
-    input:
-        tuple val(lib_id), file(reads) from reads
-    output:
-        tuple val(lib_id), file("*_{1,2}.fq") into unziped
-    
-    publishDir 'result/tmp/unzip'
-
-    """
-    if [[ ${reads[0]} == *.gz ]]; then
-        unpigz ${reads[0]} -p $task.cpus -c > ${lib_id}_1.fq
-        unpigz ${reads[1]} -p $task.cpus -c > ${lib_id}_2.fq
-    else
-        ln -s ${reads[0]} ${lib_id}_1.fq
-        ln -s ${reads[1]} ${lib_id}_2.fq
-    fi
-    """
-}
-
 process extract_PETs {
     input:
-        tuple val(lib_id), file(reads) from unziped
+        tuple val(lib_id), file(reads) from reads
 
     output:
         tuple val(lib_id), file("*.pet{1,2}.fq") into pets
@@ -0,0 +1,453 @@
+//! Discover the linker and the barcode layout from reads. Over-represented k-mers
+//! are assembled to the fixed segments of linker, reads are anchored on the most
+//! supported segment, then positions around it are classified as fixed or
+//! variable (barcode) by the base entropy, at most `max_entropy` bits for a
+//! fixed position. The consensus base is the base of a fixed position.
+
+use std::collections::{HashMap, HashSet};
+
+use bio::io::fastq;
+use clap::ArgMatches;
+
+use utils::error::{parse_arg, Error, Pos};
+use utils::open_file;
+
+const BASES: &[u8; 4] = b"ACGT";
+/// Number of the most supported segments tried as anchor.
+const MAX_ANCHORS: usize = 5;
+/// Min length of a barcode block.
+const MIN_BLOCK: usize = 4;
+
+/// Base counts of one position around the anchor.
+#[derive(Clone, Default)]
+struct Column {
+    counts: [u64; 4],
+}
+
+impl Column {
+    fn total(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    fn consensus(&self) -> (u8, f64) {
+        let (i, max) = self
+            .counts
+            .iter()
+            .enumerate()
+            .max_by_key(|(_, c)| **c)
+            .unwrap();
+        (BASES[i], *max as f64 / self.total().max(1) as f64)
+    }
+
+    /// Shannon entropy in bits, 0 for a fixed base and 2 for a random base.
+    fn entropy(&self) -> f64 {
+        let total = self.total() as f64;
+        self.counts
+            .iter()
+            .filter(|c| **c > 0)
+            .map(|c| {
+                let p = *c as f64 / total;
+                p * (1.0 / p).log2()
+            })
+            .sum()
+    }
+
+    fn is_fixed(&self, max_entropy: f64) -> bool {
+        self.entropy() <= max_entropy
+    }
+}
+
+fn base_index(b: u8) -> Option<usize> {
+    match b {
+        b'A' | b'a' => Some(0),
+        b'C' | b'c' => Some(1),
+        b'G' | b'g' => Some(2),
+        b'T' | b't' => Some(3),
+        _ => None,
+    }
+}
+
+fn sample_reads(paths: &[&str], n: usize) -> Result<Vec<Vec<u8>>, Error> {
+    let mut reads = vec![];
+    for path in paths {
+        let reader = fastq::Reader::new(open_file(path)?);
+        for (i, rec) in reader.records().take(n).enumerate() {
+            let rec = rec
+                .map_err(|e| Error::format(path, Some(Pos::Record(i as u64 + 1)), e.to_string()))?;
+            reads.push(rec.seq().to_ascii_uppercase());
+        }
+    }
+    Ok(reads)
+}
+
+/// Number of reads containing each k-mer.
+fn count_kmers(reads: &[Vec<u8>], k: usize) -> HashMap<&[u8], u64> {
+    let mut cnts = HashMap::new();
+    for read in reads {
+        let kmers: HashSet<&[u8]> = read
+            .windows(k)
+            .filter(|w| w.iter().all(|b| base_index(*b).is_some()))
+            .collect();
+        for kmer in kmers {
+            *cnts.entry(kmer).or_insert(0) += 1;
+        }
+    }
+    cnts
+}
+
+/// Extend the seed k-mer greedily with the over-represented k-mers overlapping k-1 bases.
+fn assemble(seed: &[u8], kmers: &HashMap<&[u8], u64>, used: &mut HashSet<Vec<u8>>) -> Vec<u8> {
+    let k = seed.len();
+    let mut contig = seed.to_vec();
+    used.insert(seed.to_vec());
+    for to_right in [true, false] {
+        loop {
+            let end = if to_right {
+                contig[contig.len() - k + 1..].to_vec()
+            } else {
+                contig[..k - 1].to_vec()
+            };
+            let best = BASES
+                .iter()
+                .map(|b| {
+                    let mut next = end.clone();
+                    if to_right {
+                        next.push(*b);
+                    } else {
+                        next.insert(0, *b);
+                    }
+                    next
+                })
+                .filter(|next| !used.contains(next))
+                .filter_map(|next| kmers.get(&next[..]).map(|c| (*c, next)))
+                .max();
+            match best {
+                Some((_, next)) => {
+                    if to_right {
+                        contig.push(next[k - 1]);
+                    } else {
+                        contig.insert(0, next[0]);
+                    }
+                    used.insert(next);
+                }
+                None => break,
+            }
+        }
+    }
+    contig
+}
+
+/// Base counts at each offset to the anchor, from `-flank` to `anchor.len() + flank`.
+fn profile(reads: &[Vec<u8>], anchor: &[u8], flank: usize) -> (Vec<Column>, u64) {
+    let mut columns = vec![Column::default(); anchor.len() + 2 * flank];
+    let mut n_anchored = 0;
+    for read in reads {
+        let pos = match read.windows(anchor.len()).position(|w| w == anchor) {
+            Some(p) => p,
+            None => continue,
+        };
+        n_anchored += 1;
+        for (i, col) in columns.iter_mut().enumerate() {
+            let j = pos as i64 - flank as i64 + i as i64;
+            if j < 0 || j >= read.len() as i64 {
+                continue;
+            }
+            if let Some(b) = base_index(read[j as usize]) {
+                col.counts[b] += 1;
+            }
+        }
+    }
+    (columns, n_anchored)
+}
+
+/// Linker called from the reads anchored on one fixed segment.
+struct Candidate {
+    anchor: Vec<u8>,
+    n_anchored: u64,
+    linker: String,
+    /// base counts of each position of linker
+    columns: Vec<Column>,
+    /// start and end of the variable blocks in linker
+    blocks: Vec<(usize, usize)>,
+}
+
+/// Extend from the anchor to both sides while the variable runs are shorter than
+/// `max_block`, the linker ends with the last fixed bases.
+fn call_linker(
+    reads: &[Vec<u8>],
+    anchor: &[u8],
+    max_entropy: f64,
+    max_block: usize,
+    enzyme: Option<&str>,
+) -> Candidate {
+    let flank = 3 * max_block + 40;
+    let (columns, n_anchored) = profile(reads, anchor, flank);
+    let min_cover = (n_anchored / 2).max(1);
+    let is_fixed = |c: &Column| c.is_fixed(max_entropy);
+    let mut start = flank;
+    let mut end = flank + anchor.len();
+    let mut i = end;
+    while i < columns.len() && columns[i].total() >= min_cover {
+        if is_fixed(&columns[i]) {
+            end = i + 1;
+        } else if i - end >= max_block {
+            break;
+        }
+        i += 1;
+    }
+    let mut i = start;
+    while i > 0 && columns[i - 1].total() >= min_cover {
+        if is_fixed(&columns[i - 1]) {
+            start = i - 1;
+        } else if start - (i - 1) > max_block {
+            break;
+        }
+        i -= 1;
+    }
+
+    let mut linker: String = columns[start..end]
+        .iter()
+        .map(|c| {
+            if is_fixed(c) {
+                c.consensus().0 as char
+            } else {
+                'N'
+            }
+        })
+        .collect();
+    // Remove the enzyme site next to the PETs, for T^TA^A the linker is flanked
+    // by TTA and TAA, which may be partially read as fixed bases.
+    if let Some(enzyme) = enzyme {
+        let parts: Vec<&str> = enzyme.split('^').collect();
+        if parts.len() == 3 {
+            let head = format!("{}{}", parts[0], parts[1]);
+            let tail = format!("{}{}", parts[1], parts[2]);
+            if let Some(n) = (2..=head.len())
+                .rev()
+                .find(|n| linker.starts_with(&head[head.len() - n..]))
+            {
+                linker.drain(..n);
+                start += n;
+            }
+            if let Some(n) = (2..=tail.len())
+                .rev()
+                .find(|n| linker.ends_with(&tail[..*n]))
+            {
+                linker.truncate(linker.len() - n);
+                end -= n;
+            }
+        }
+    }
+
+    let mut blocks = vec![];
+    let bytes = linker.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'N' {
+            let s = i;
+            while i < bytes.len() && bytes[i] == b'N' {
+                i += 1;
+            }
+            blocks.push((s, i));
+        } else {
+            i += 1;
+        }
+    }
+    Candidate {
+        anchor: anchor.to_vec(),
+        n_anchored,
+        linker,
+        columns: columns[start..end].to_vec(),
+        blocks,
+    }
+}
+
+pub fn run(matches: &ArgMatches) -> Result<(), Error> {
+    let mut paths = vec![matches.value_of("fq1").unwrap()];
+    paths.extend(matches.value_of("fq2"));
+    let n_sample: usize = parse_arg("sample", matches.value_of("sample").unwrap_or("10000"))?;
+    let k: usize = parse_arg("kmer", matches.value_of("kmer").unwrap_or("6"))?;
+    let min_support: f64 = parse_arg(
+        "min_support",
+        matches.value_of("min_support").unwrap_or("0.1"),
+    )?;
+    let max_entropy: f64 = parse_arg(
+        "max_entropy",
+        matches.value_of("max_entropy").unwrap_or("1.0"),
+    )?;
+    let max_block: usize = parse_arg("max_block", matches.value_of("max_block").unwrap_or("12"))?;
+    let enzyme = matches.value_of("enzyme");
+    if k == 0 || k > 31 {
+        return Err(Error::arg("kmer", "should be in 1 to 31"));
+    }
+
+    let reads = sample_reads(&paths, n_sample)?;
+    let n_reads = reads.len() as f64;
+    let mut msg = format!("Sampled reads: {}\n\n", reads.len());
+
+    // k-mers in much more reads than expected by chance
+    let kmers = count_kmers(&reads, k);
+    let mean_len = reads.iter().map(|r| r.len()).sum::<usize>() as f64 / n_reads.max(1.0);
+    let expected = (mean_len - k as f64 + 1.0).max(0.0) / 4f64.powi(k as i32);
+    let mut over: Vec<(&[u8], u64)> = kmers
+        .iter()
+        .filter(|(_, c)| {
+            let support = **c as f64 / n_reads;
+            support >= min_support && support >= 10.0 * expected
+        })
+        .map(|(k, c)| (*k, *c))
+        .collect();
+    over.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+    msg.push_str(&format!(
+        "Over-represented {}-mers (expected support {:.4}):\n",
+        k, expected
+    ));
+    for (kmer, cnt) in over.iter().take(20) {
+        let seq = String::from_utf8_lossy(kmer);
+        msg.push_str(&format!(
+            "{}\t{}\t{:.2}%\n",
+            seq,
+            cnt,
+            *cnt as f64 * 100.0 / n_reads
+        ));
+    }
+    if over.is_empty() {
+        println!("{}No over-represented k-mers, no linker found.", msg);
+        return Ok(());
+    }
+
+    let over_map: HashMap<&[u8], u64> = over.iter().cloned().collect();
+    let mut used = HashSet::new();
+    let mut segments = vec![];
+    for (kmer, _) in &over {
+        if used.contains(*kmer) {
+            continue;
+        }
+        let contig = assemble(kmer, &over_map, &mut used);
+        let support = reads
+            .iter()
+            .filter(|r| r.windows(contig.len()).any(|w| w == &contig[..]))
+            .count();
+        segments.push((contig, support));
+    }
+    segments.sort_by_key(|s| std::cmp::Reverse(s.1));
+    msg.push_str("\nFixed segments:\n");
+    for (seg, support) in &segments {
+        let seq = String::from_utf8_lossy(seg);
+        msg.push_str(&format!(
+            "{}\t{}\t{:.2}%\n",
+            seq,
+            support,
+            *support as f64 * 100.0 / n_reads
+        ));
+    }
+
+    // Each of the most supported segments is tried as anchor, segments of constant
+    // sequences other than linker (adapters, primers) have no variable blocks.
+    msg.push_str("\nCandidates:\nanchor\tanchored\tlinker\n");
+    let mut candidates = vec![];
+    for (seg, _) in segments.iter().take(MAX_ANCHORS) {
+        let cand = call_linker(&reads, seg, max_entropy, max_block, enzyme);
+        msg.push_str(&format!(
+            "{}\t{}\t{}\n",
+            String::from_utf8_lossy(seg),
+            cand.n_anchored,
+            cand.linker
+        ));
+        candidates.push(cand);
+    }
+    let best = candidates
+        .iter()
+        .filter(|c| !c.blocks.is_empty() && c.blocks.iter().all(|b| b.1 - b.0 >= MIN_BLOCK))
+        .max_by_key(|c| c.n_anchored)
+        .unwrap_or(&candidates[0]);
+
+    msg.push_str(&format!(
+        "\nAnchor: {}\tanchored reads: {}\n",
+        String::from_utf8_lossy(&best.anchor),
+        best.n_anchored
+    ));
+    msg.push_str("\nPosition profile:\noffset\tconsensus\tfreq\tentropy\tclass\n");
+    for (i, col) in best.columns.iter().enumerate() {
+        let (base, freq) = col.consensus();
+        msg.push_str(&format!(
+            "{}\t{}\t{:.2}\t{:.2}\t{}\n",
+            i,
+            base as char,
+            freq,
+            col.entropy(),
+            if col.is_fixed(max_entropy) {
+                "fixed"
+            } else {
+                "variable"
+            }
+        ));
+    }
+    msg.push_str("\nN blocks:\n");
+    for (s, e) in &best.blocks {
+        msg.push_str(&format!("{}-{}\t{}\n", s, e, e - s));
+    }
+    msg.push_str(&format!(
+        "\nProposed linker: --linker {}\t(anchored in {:.2}% reads)\n",
+        best.linker,
+        best.n_anchored as f64 * 100.0 / n_reads
+    ));
+    print!("{}", msg);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_call_linker() {
+        // linker with two barcode blocks between random PETs
+        let mut state: u64 = 42;
+        let mut random_seq = |n: usize| -> Vec<u8> {
+            (0..n)
+                .map(|_| {
+                    state = state.wrapping_mul(6364136223846793005).wrapping_add(1);
+                    BASES[(state >> 33) as usize % 4]
+                })
+                .collect()
+        };
+        let reads: Vec<Vec<u8>> = (0..200)
+            .map(|_| {
+                let mut read = random_seq(30);
+                read.extend(b"TTAGTCGGA");
+                read.extend(random_seq(6));
+                read.extend(b"GCTAGC");
+                read.extend(random_seq(6));
+                read.extend(b"TCCGACTAA");
+                read.extend(random_seq(30));
+                read
+            })
+            .collect();
+        let cand = call_linker(&reads, b"GCTAGC", 1.0, 12, Some("T^TA^A"));
+        assert_eq!(cand.n_anchored, 200);
+        assert_eq!(cand.linker, "GTCGGANNNNNNGCTAGCNNNNNNTCCGAC");
+        assert_eq!(cand.blocks, vec![(6, 12), (18, 24)]);
+    }
+
+    #[test]
+    fn test_is_fixed() {
+        // the dominant base is in 80% of reads, but the rest is spread
+        let spread = Column {
+            counts: [80, 7, 7, 6],
+        };
+        assert_eq!(spread.consensus(), (b'A', 0.8));
+        assert!(spread.entropy() > 1.0);
+        assert!(!spread.is_fixed(1.0));
+        // two bases only, e.g. a sequencing error or SNP at a fixed base
+        let two_bases = Column {
+            counts: [75, 25, 0, 0],
+        };
+        assert!(two_bases.entropy() < 1.0);
+        assert!(two_bases.is_fixed(1.0));
+        assert!(Column {
+            counts: [0, 0, 9, 0]
+        }
+        .is_fixed(0.0));
+    }
+}
@@ -0,0 +1,216 @@
+use std::collections::BTreeMap;
+use std::thread;
+
+use bio::alignment::pairwise::Aligner;
+
+use utils::error::Error;
+
+use crate::reader::{LanesReader, ReadPair};
+use crate::{match_score, Counter, Extractor};
+
+const RATIO_GRID: [f32; 8] = [0.6, 0.65, 0.7, 0.75, 0.8, 0.85, 0.9, 0.95];
+const MIN_PET_LEN_GRID: [usize; 5] = [8, 10, 12, 14, 16];
+/// Max fraction of the valid pairs dropped by the recommended min_pet_len.
+const MAX_LOSS: f64 = 0.01;
+/// Max fraction of the PETs cut by the recommended max_pet_len.
+const MAX_CUT: f64 = 0.05;
+
+/// Small xorshift generator, the sample is the same between runs.
+struct Rng(u64);
+
+impl Rng {
+    fn below(&mut self, n: u64) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0 % n
+    }
+}
+
+/// Take the first `n` pairs, or a reservoir sample of `n` pairs from all reads.
+pub fn sample_pairs(
+    reader: &mut LanesReader,
+    n: usize,
+    reservoir: bool,
+) -> Result<Vec<ReadPair>, Error> {
+    let mut pairs = Vec::with_capacity(n);
+    let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+    let mut seen: u64 = 0;
+    while let Some((_, pair)) = reader.next_pair()? {
+        seen += 1;
+        if pairs.len() < n {
+            pairs.push(pair);
+        } else if !reservoir {
+            break;
+        } else {
+            let i = rng.below(seen) as usize;
+            if i < n {
+                pairs[i] = pair;
+            }
+        }
+    }
+    Ok(pairs)
+}
+
+/// Best linker alignment score on the fixed bases of linker,
+/// and the start of the linker in the read.
+fn best_linker_score(extractor: &Extractor, seq: &[u8]) -> (usize, usize) {
+    let mut aligner =
+        Aligner::with_capacity(seq.len(), extractor.linker.len(), -1, -1, match_score);
+    let aln = aligner.semiglobal(&extractor.linker, seq);
+    let score = (aln.score - extractor.n_in_linker as i32).max(0);
+    (score as usize, aln.ystart)
+}
+
+fn percentile(cnts: &BTreeMap<usize, u64>, p: f64) -> usize {
+    let n: u64 = cnts.values().sum();
+    let mut acc = 0;
+    for (k, v) in cnts {
+        acc += v;
+        if acc as f64 >= n as f64 * p {
+            return *k;
+        }
+    }
+    0
+}
+
+/// Shorter PET length of the first PET pair of each valid read pair,
+/// extracted with the score ratio threshold `ratio` and no min PET length.
+fn valid_pet_lens(extractor: &Extractor, pairs: &[ReadPair], ratio: f32) -> Vec<usize> {
+    let mut ext = extractor.clone();
+    ext.score_ratio_thresh = ratio;
+    ext.min_pet_len = 1;
+    let mut counter = Counter::new();
+    let mut lens = vec![];
+    for pair in pairs {
+        let rec2 = pair.rec2.clone();
+        if let Ok(recs) = ext.extract_pet(pair.rec1.clone(), rec2, &pair.index, &mut counter) {
+            lens.push(recs[0].0.seq().len().min(recs[0].1.seq().len()));
+        }
+    }
+    lens
+}
+
+/// Longest min PET length below `limit` which drops at most `MAX_LOSS` of the
+/// valid pairs, `lens` are the shorter PET lengths of them.
+fn recommend_min_pet_len(lens: &[usize], limit: usize) -> usize {
+    let max_dropped = lens.len() as f64 * MAX_LOSS;
+    (1..limit)
+        .rev()
+        .find(|l| lens.iter().filter(|len| *len < l).count() as f64 <= max_dropped)
+        .unwrap_or(1)
+}
+
+/// Summaries of the sampled pairs and the recommended settings,
+/// the grid is computed by at most `threads` threads.
+pub fn report(extractor: &Extractor, pairs: &[ReadPair], threads: usize) -> String {
+    let n_fixed = extractor.linker.len() - extractor.n_in_linker;
+    let mut score_hist = vec![0u64; n_fixed + 1];
+    let mut pet_lens = BTreeMap::new();
+    for pair in pairs {
+        let mut reads = vec![&pair.rec1];
+        reads.extend(pair.rec2.as_ref());
+        for rec in reads {
+            let (score, start) = best_linker_score(extractor, rec.seq());
+            score_hist[score.min(n_fixed)] += 1;
+            if score as f32 >= extractor.score_ratio_thresh * n_fixed as f32 {
+                *pet_lens.entry(start).or_insert(0) += 1;
+            }
+        }
+    }
+
+    let mut msg = format!("Dry run on {} sampled read pairs.\n\n", pairs.len());
+    msg.push_str("Linker score ratio histogram:\n");
+    for (score, cnt) in score_hist.iter().enumerate() {
+        msg.push_str(&format!("{:.2}\t{}\n", score as f32 / n_fixed as f32, cnt));
+    }
+    msg.push_str("\nPET length before length filters:\n");
+    for (len, cnt) in &pet_lens {
+        msg.push_str(&format!("{}\t{}\n", len, cnt));
+    }
+
+    msg.push_str("\nValid rate, score_ratio_thresh by min_pet_len of the first PET pair:\n");
+    let min_lens: Vec<usize> = MIN_PET_LEN_GRID
+        .iter()
+        .copied()
+        .filter(|l| *l < extractor.pet_cut_len)
+        .collect();
+    msg.push_str("ratio");
+    for l in &min_lens {
+        msg.push_str(&format!("\t{}", l));
+    }
+    msg.push('\n');
+    let mut rows: Vec<Vec<usize>> = Vec::with_capacity(RATIO_GRID.len());
+    for ratios in RATIO_GRID.chunks(threads.max(1)) {
+        thread::scope(|s| {
+            let handles: Vec<_> = ratios
+                .iter()
+                .map(|r| s.spawn(move || valid_pet_lens(extractor, pairs, *r)))
+                .collect();
+            rows.extend(handles.into_iter().map(|h| h.join().unwrap()));
+        });
+    }
+    for (ratio, lens) in RATIO_GRID.iter().zip(rows) {
+        msg.push_str(&format!("{:.2}", ratio));
+        for l in &min_lens {
+            let n_valid = lens.iter().filter(|len| *len >= l).count();
+            let rate = n_valid as f64 * 100.0 / pairs.len().max(1) as f64;
+            msg.push_str(&format!("\t{:.2}%", rate));
+        }
+        msg.push('\n');
+    }
+
+    // Threshold at the valley between the random alignments and the linkers,
+    // count two adjacent scores together, a mismatch changes score by 2.
+    let lo = (RATIO_GRID[0] * n_fixed as f32).ceil() as usize;
+    let windows: Vec<(usize, u64)> = (lo..n_fixed)
+        .map(|s| (s, score_hist[s] + score_hist[s + 1]))
+        .collect();
+    msg.push_str("\nRecommended settings:\n");
+    let mut ratio = extractor.score_ratio_thresh;
+    if let Some(min_cnt) = windows.iter().map(|w| w.1).min() {
+        let valley: Vec<usize> = windows
+            .iter()
+            .filter(|w| w.1 == min_cnt)
+            .map(|w| w.0)
+            .collect();
+        // between the two scores of the window
+        ratio = (valley[valley.len() / 2] as f32 + 0.5) / n_fixed as f32;
+        msg.push_str(&format!("--score_ratio_thresh {:.2}\n", ratio));
+    }
+    if !pet_lens.is_empty() {
+        let max_len = percentile(&pet_lens, 1.0 - MAX_CUT).max(3);
+        let lens = valid_pet_lens(extractor, pairs, ratio);
+        let min_len = recommend_min_pet_len(&lens, max_len - 1);
+        msg.push_str(&format!(
+            "--min_pet_len {} --max_pet_len {} --pet_cut_len {}\n",
+            min_len,
+            max_len,
+            max_len - 1
+        ));
+        msg.push_str(&format!(
+            "min_pet_len is the longest that drops at most {}% of the valid pairs \
+             at score_ratio_thresh {:.2},\nmax_pet_len is the shortest that at most {}% \
+             of the PETs are longer than, they are cut to pet_cut_len.\n",
+            MAX_LOSS * 100.0,
+            ratio,
+            MAX_CUT * 100.0
+        ));
+    }
+    msg
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recommend_min_pet_len() {
+        // 1 of 100 pairs shorter than 18, 2 shorter than 19
+        let mut lens = vec![20; 98];
+        lens.extend([17, 18]);
+        assert_eq!(recommend_min_pet_len(&lens, 20), 18);
+        assert_eq!(recommend_min_pet_len(&lens, 15), 14);
+        assert_eq!(recommend_min_pet_len(&[5, 5], 20), 5);
+    }
+}
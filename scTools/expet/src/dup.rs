@@ -0,0 +1,138 @@
+//! Sequence duplicates of PET pairs, estimated before the alignment.
+//! A pair is identified by the hash of its barcode and the sequences of PET1 and PET2.
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::ops;
+
+/// Bits of hash used as the register index of HyperLogLog.
+const PRECISION: u32 = 10;
+const N_REGISTERS: usize = 1 << PRECISION;
+/// Hashes are kept exactly until there are more unique ones than this,
+/// most barcodes of sequencing errors have only a few pairs.
+const MAX_SPARSE: usize = 128;
+
+/// Total and unique counts of the PET pairs of a barcode,
+/// memory is bounded by the HyperLogLog registers.
+#[derive(Clone, Debug, Default)]
+pub struct DupSketch {
+    pub total: u64,
+    /// Sorted hashes in the sparse mode.
+    sparse: Vec<u64>,
+    /// HyperLogLog registers, empty in the sparse mode.
+    registers: Vec<u8>,
+}
+
+impl DupSketch {
+    pub fn insert(&mut self, hash: u64) {
+        self.total += 1;
+        self.add_hash(hash);
+    }
+
+    fn add_hash(&mut self, hash: u64) {
+        if !self.registers.is_empty() {
+            let i = (hash >> (64 - PRECISION)) as usize;
+            let rank = ((hash << PRECISION).leading_zeros() + 1).min(64 - PRECISION + 1) as u8;
+            self.registers[i] = self.registers[i].max(rank);
+        } else if let Err(i) = self.sparse.binary_search(&hash) {
+            self.sparse.insert(i, hash);
+            if self.sparse.len() > MAX_SPARSE {
+                self.densify();
+            }
+        }
+    }
+
+    fn densify(&mut self) {
+        self.registers = vec![0; N_REGISTERS];
+        for hash in std::mem::take(&mut self.sparse) {
+            self.add_hash(hash);
+        }
+    }
+
+    /// Exact count in the sparse mode, the HyperLogLog estimation otherwise.
+    pub fn unique(&self) -> u64 {
+        if self.registers.is_empty() {
+            return self.sparse.len() as u64;
+        }
+        let m = N_REGISTERS as f64;
+        let alpha = 0.7213 / (1.0 + 1.079 / m);
+        let sum: f64 = self.registers.iter().map(|r| 2f64.powi(-(*r as i32))).sum();
+        let estimate = alpha * m * m / sum;
+        let zeros = self.registers.iter().filter(|r| **r == 0).count();
+        let estimate = if estimate <= 2.5 * m && zeros > 0 {
+            // linear counting for the small cardinality
+            m * (m / zeros as f64).ln()
+        } else {
+            estimate
+        };
+        (estimate.round() as u64).min(self.total)
+    }
+}
+
+impl ops::AddAssign for DupSketch {
+    fn add_assign(&mut self, other: Self) {
+        self.total += other.total;
+        if !self.registers.is_empty() && !other.registers.is_empty() {
+            for (r, o) in self.registers.iter_mut().zip(&other.registers) {
+                *r = (*r).max(*o);
+            }
+            return;
+        }
+        if !other.registers.is_empty() {
+            let sparse = std::mem::take(&mut self.sparse);
+            self.registers = other.registers;
+            for hash in sparse {
+                self.add_hash(hash);
+            }
+            return;
+        }
+        for hash in other.sparse {
+            self.add_hash(hash);
+        }
+    }
+}
+
+/// Barcode in the ID of PET record, between the last '/' and the UMI.
+pub fn barcode_of(id: &str) -> &str {
+    match id.rsplit_once('/') {
+        Some((_, b)) => b.split(':').next().unwrap(),
+        None => "",
+    }
+}
+
+pub fn pet_hash(barcode: &str, seq1: &[u8], seq2: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    barcode.hash(&mut hasher);
+    seq1.hash(&mut hasher);
+    seq2.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dup_sketch() {
+        let mut small = DupSketch::default();
+        for i in 0..100 {
+            small.insert(pet_hash("AC", format!("{}", i % 50).as_bytes(), b"GT"));
+        }
+        assert_eq!((small.total, small.unique()), (100, 50));
+
+        let mut large = DupSketch::default();
+        for i in 0..20000 {
+            large.insert(pet_hash("AC", format!("{}", i).as_bytes(), b"GT"));
+        }
+        let unique = large.unique() as f64;
+        assert!((unique - 20000.0).abs() < 20000.0 * 0.1, "{}", unique);
+
+        // pairs of the small sketch are also in the large one
+        large += small;
+        assert_eq!(large.total, 20100);
+        assert!((large.unique() as f64 - unique).abs() < 20000.0 * 0.01);
+
+        assert_eq!(barcode_of("r1/1_c2/AC-GT-AC-GT:TTGA"), "AC-GT-AC-GT");
+        assert_eq!(barcode_of("r1"), "");
+    }
+}
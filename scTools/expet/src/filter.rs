@@ -0,0 +1,191 @@
+/// Offset of the Phred quality encoding.
+pub const PHRED_OFFSET: u8 = 33;
+
+/// Quality and complexity trimming and filtering of reads and PETs.
+/// PETs are oriented toward the linker, so trimming is applied at their start.
+#[derive(Clone, Default)]
+pub struct QualFilter {
+    /// Quality cutoff of trimming, no trimming if `None`.
+    pub trim_qual: Option<u8>,
+    /// Use sliding window of this size for trimming,
+    /// otherwise use the algorithm of cutadapt's `-q` option.
+    pub trim_window: Option<usize>,
+    pub max_n_ratio: Option<f32>,
+    pub min_mean_qual: Option<f32>,
+    /// Bases of the homopolymer tail trimmed from reads' 3' end,
+    /// like `b"G"` for two-colour chemistry, no trimming if empty.
+    pub poly_x_bases: Vec<u8>,
+    pub poly_x_min_len: usize,
+    pub max_dust_score: Option<f32>,
+}
+
+impl QualFilter {
+    /// Number of low quality bases to trim from the start of PET.
+    pub fn trim_len(&self, qual: &[u8]) -> usize {
+        match (self.trim_qual, self.trim_window) {
+            (None, _) => 0,
+            (Some(cutoff), None) => qual_trim_start(qual, cutoff),
+            (Some(cutoff), Some(window)) => window_trim_start(qual, cutoff, window),
+        }
+    }
+
+    pub fn is_too_many_n(&self, seq: &[u8]) -> bool {
+        match self.max_n_ratio {
+            Some(max_ratio) if !seq.is_empty() => {
+                let n = seq.iter().filter(|b| **b == b'N' || **b == b'n').count();
+                n as f32 / seq.len() as f32 > max_ratio
+            }
+            _ => false,
+        }
+    }
+
+    pub fn is_low_qual(&self, qual: &[u8]) -> bool {
+        match self.min_mean_qual {
+            Some(min_qual) if !qual.is_empty() => mean_qual(qual) < min_qual,
+            _ => false,
+        }
+    }
+
+    /// Length of the poly-X tail to trim from the 3' end of read.
+    pub fn poly_x_len(&self, seq: &[u8]) -> usize {
+        let mut max_len = 0;
+        for b in &self.poly_x_bases {
+            let len = poly_x_tail_len(seq, *b);
+            if len >= self.poly_x_min_len && len > max_len {
+                max_len = len;
+            }
+        }
+        max_len
+    }
+
+    pub fn is_low_complexity(&self, seq: &[u8]) -> bool {
+        match self.max_dust_score {
+            Some(max_score) => dust_score(seq) > max_score,
+            None => false,
+        }
+    }
+}
+
+pub fn mean_qual(qual: &[u8]) -> f32 {
+    let sum: u64 = qual
+        .iter()
+        .map(|q| q.saturating_sub(PHRED_OFFSET) as u64)
+        .sum();
+    sum as f32 / qual.len() as f32
+}
+
+/// Trim the start of read like cutadapt's `-q` (BWA's) algorithm,
+/// return the number of bases to be removed.
+pub fn qual_trim_start(qual: &[u8], cutoff: u8) -> usize {
+    let mut s: i64 = 0;
+    let mut max_s: i64 = 0;
+    let mut trim = 0;
+    for (i, q) in qual.iter().enumerate() {
+        s += cutoff as i64 - q.saturating_sub(PHRED_OFFSET) as i64;
+        if s < 0 {
+            break;
+        }
+        if s > max_s {
+            max_s = s;
+            trim = i + 1;
+        }
+    }
+    trim
+}
+
+/// Scan windows from the end of read, remove all bases before the first
+/// window which mean quality is lower than `cutoff`.
+pub fn window_trim_start(qual: &[u8], cutoff: u8, window: usize) -> usize {
+    if window == 0 || qual.len() < window {
+        return 0;
+    }
+    for end in (window..=qual.len()).rev() {
+        if mean_qual(&qual[end - window..end]) < cutoff as f32 {
+            return end;
+        }
+    }
+    0
+}
+
+/// Length of the tail composed of base `b`, allow one mismatch in every 8 bases like fastp.
+pub fn poly_x_tail_len(seq: &[u8], b: u8) -> usize {
+    let mut mismatch = 0;
+    let mut len = 0;
+    for (i, c) in seq.iter().rev().enumerate() {
+        if *c == b {
+            len = i + 1;
+        } else {
+            mismatch += 1;
+            if mismatch > (i + 1) / 8 {
+                break;
+            }
+        }
+    }
+    len
+}
+
+/// Low complexity score of the sequence like DUST: sum of c_t * (c_t - 1) / 2
+/// over the counts of all triplets, divided by (number of triplets - 1).
+pub fn dust_score(seq: &[u8]) -> f32 {
+    if seq.len() < 4 {
+        return 0.0;
+    }
+    let mut counts = [0u32; 64];
+    let mut n_triplets = 0;
+    for w in seq.windows(3) {
+        let mut code = 0;
+        let mut valid = true;
+        for b in w {
+            code = code * 4
+                + match b {
+                    b'A' | b'a' => 0,
+                    b'C' | b'c' => 1,
+                    b'G' | b'g' => 2,
+                    b'T' | b't' => 3,
+                    _ => {
+                        valid = false;
+                        0
+                    }
+                };
+        }
+        if valid {
+            counts[code] += 1;
+            n_triplets += 1;
+        }
+    }
+    if n_triplets < 2 {
+        return 0.0;
+    }
+    let sum: u32 = counts.iter().map(|c| c * c.saturating_sub(1) / 2).sum();
+    sum as f32 / (n_triplets - 1) as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn to_qual(q: &[u8]) -> Vec<u8> {
+        q.iter().map(|q| q + PHRED_OFFSET).collect()
+    }
+
+    #[test]
+    fn test_qual_trim_start() {
+        let qual = to_qual(&[2, 2, 30, 5, 30, 30, 30, 30]);
+        assert_eq!(qual_trim_start(&qual, 20), 4);
+        assert_eq!(qual_trim_start(&to_qual(&[30, 2, 30]), 20), 0);
+    }
+
+    #[test]
+    fn test_window_trim_start() {
+        let qual = to_qual(&[2, 2, 30, 30, 2, 30, 30, 30, 30]);
+        assert_eq!(window_trim_start(&qual, 20, 2), 6);
+        assert_eq!(window_trim_start(&qual, 20, 20), 0);
+    }
+
+    #[test]
+    fn test_poly_x() {
+        assert_eq!(poly_x_tail_len(b"ACCAGGGGGGAGGGGGGGG", b'G'), 15);
+        assert_eq!(poly_x_tail_len(b"ACGTACGT", b'G'), 0);
+        assert!(dust_score(b"AAAAAAAAAAAAAAAAAAAA") > dust_score(b"ACGTTGCAAGCTTACGGATC"));
+    }
+}
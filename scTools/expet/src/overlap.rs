@@ -0,0 +1,59 @@
+use bio::alphabets::dna::revcomp;
+
+/// Find the overlap of R1 and the reverse complement of R2 like fastp,
+/// return the insert size. Offset of R2 relative to R1 is searched from 0 to the
+/// positive side first, then the negative side, which means adapter in both reads.
+pub fn find_insert(seq1: &[u8], seq2: &[u8], min_overlap: usize, max_diff: usize) -> Option<usize> {
+    let (len1, len2) = (seq1.len() as i64, seq2.len() as i64);
+    let min_overlap = min_overlap as i64;
+    if len1 < min_overlap || len2 < min_overlap {
+        return None;
+    }
+    let rc2 = revcomp(seq2);
+    let offsets = (0..=len1 - min_overlap).chain((-(len2 - min_overlap)..0).rev());
+    for offset in offsets {
+        let start = offset.max(0);
+        let end = len1.min(offset + len2);
+        let overlap = end - start;
+        if overlap < min_overlap {
+            continue;
+        }
+        // allow one mismatch in every 5 bases, at most `max_diff`
+        let max_diff = max_diff.min(overlap as usize / 5);
+        let mut diff = 0;
+        for i in start..end {
+            if seq1[i as usize] != rc2[(i - offset) as usize] {
+                diff += 1;
+                if diff > max_diff {
+                    break;
+                }
+            }
+        }
+        if diff <= max_diff {
+            return Some((offset + len2) as usize);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_insert() {
+        let insert = b"ACGTTGCAAGCTTACGGATCCATGACTGGTACCTAGGCATGCAAGTTCAGCTGA";
+        let adapter = b"AGATCGGAAGAGC";
+        // short insert, both reads run into the adapter
+        let mut r1 = insert.to_vec();
+        r1.extend(adapter);
+        let mut r2 = revcomp(&insert[..]);
+        r2.extend(adapter);
+        assert_eq!(find_insert(&r1, &r2, 30, 5), Some(insert.len()));
+        // long insert, reads overlap in the middle
+        let r1 = &insert[..40];
+        let r2 = revcomp(&insert[10..]);
+        assert_eq!(find_insert(r1, &r2, 30, 5), Some(insert.len()));
+        assert_eq!(find_insert(&insert[..30], &insert[..30], 30, 5), None);
+    }
+}
@@ -0,0 +1,143 @@
+use std::ops;
+
+use crate::filter::PHRED_OFFSET;
+
+/// Per-position counts of A, C, G, T, N and the sum of base qualities,
+/// like the per base sequence content and quality of FastQC.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PositionStats {
+    base_cnts: Vec<[u64; 5]>,
+    qual_sums: Vec<u64>,
+}
+
+impl PositionStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, seq: &[u8], qual: &[u8]) {
+        if seq.len() > self.base_cnts.len() {
+            self.base_cnts.resize(seq.len(), [0; 5]);
+            self.qual_sums.resize(seq.len(), 0);
+        }
+        for (i, (b, q)) in seq.iter().zip(qual).enumerate() {
+            let j = match b {
+                b'A' | b'a' => 0,
+                b'C' | b'c' => 1,
+                b'G' | b'g' => 2,
+                b'T' | b't' => 3,
+                _ => 4,
+            };
+            self.base_cnts[i][j] += 1;
+            self.qual_sums[i] += q.saturating_sub(PHRED_OFFSET) as u64;
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.base_cnts.is_empty()
+    }
+
+    /// One line per position: position, ratios of A C G T N, and mean quality.
+    pub fn table(&self) -> String {
+        let mut msg = "pos\tA\tC\tG\tT\tN\tmean_qual\n".to_string();
+        for (i, (cnts, qual_sum)) in self.base_cnts.iter().zip(&self.qual_sums).enumerate() {
+            let n: u64 = cnts.iter().sum();
+            msg.push_str(&format!("{}", i + 1));
+            for c in cnts {
+                msg.push_str(&format!("\t{:.3}", *c as f64 / n as f64));
+            }
+            msg.push_str(&format!("\t{:.2}\n", *qual_sum as f64 / n as f64));
+        }
+        msg
+    }
+}
+
+impl ops::Add<PositionStats> for PositionStats {
+    type Output = PositionStats;
+
+    fn add(self, _rhs: PositionStats) -> PositionStats {
+        let (mut long, short) = if self.base_cnts.len() >= _rhs.base_cnts.len() {
+            (self, _rhs)
+        } else {
+            (_rhs, self)
+        };
+        for (i, cnts) in short.base_cnts.iter().enumerate() {
+            for (j, c) in cnts.iter().enumerate() {
+                long.base_cnts[i][j] += c;
+            }
+            long.qual_sums[i] += short.qual_sums[i];
+        }
+        long
+    }
+}
+
+/// Merge the stats of blocks, the lists may have different lengths.
+pub fn add_stats_vec(a: Vec<PositionStats>, b: Vec<PositionStats>) -> Vec<PositionStats> {
+    let (mut long, short) = if a.len() >= b.len() { (a, b) } else { (b, a) };
+    for (i, s) in short.into_iter().enumerate() {
+        long[i] = std::mem::take(&mut long[i]) + s;
+    }
+    long
+}
+
+/// Counts of PET1 and PET2 ending with the complete enzyme site, the half-site,
+/// or neither, at the end next to the linker.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SiteEnds {
+    pub pet1: [u64; 3],
+    pub pet2: [u64; 3],
+}
+
+pub const SITE_END_NAMES: [&str; 3] = ["site", "half_site", "neither"];
+
+impl SiteEnds {
+    pub fn total(&self) -> u64 {
+        self.pet1.iter().sum()
+    }
+}
+
+impl ops::AddAssign for SiteEnds {
+    fn add_assign(&mut self, other: Self) {
+        for i in 0..3 {
+            self.pet1[i] += other.pet1[i];
+            self.pet2[i] += other.pet2[i];
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_position_stats() {
+        let mut s1 = PositionStats::new();
+        s1.add(b"ACGT", b"IIII");
+        let mut s2 = PositionStats::new();
+        s2.add(b"AN", b"I#");
+        let s = s1 + s2;
+        let table = s.table();
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines.len(), 5);
+        assert_eq!(lines[1], "1\t1.000\t0.000\t0.000\t0.000\t0.000\t40.00");
+        assert_eq!(lines[2], "2\t0.000\t0.500\t0.000\t0.000\t0.500\t21.00");
+        assert_eq!(lines[4], "4\t0.000\t0.000\t0.000\t1.000\t0.000\t40.00");
+        let v = add_stats_vec(vec![s.clone()], vec![PositionStats::new(), s.clone()]);
+        assert_eq!(v, vec![s.clone(), s]);
+    }
+
+    #[test]
+    fn test_site_ends() {
+        let mut a = SiteEnds {
+            pet1: [1, 2, 0],
+            pet2: [0, 3, 0],
+        };
+        a += SiteEnds {
+            pet1: [0, 0, 1],
+            pet2: [1, 0, 0],
+        };
+        assert_eq!(a.pet1, [1, 2, 1]);
+        assert_eq!(a.pet2, [1, 3, 0]);
+        assert_eq!(a.total(), 4);
+    }
+}
@@ -0,0 +1,511 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{self, prelude::*};
+
+use bio::io::fastq;
+use bio::io::fastq::Record;
+use log::info;
+
+use utils::error::Error;
+use utils::open_file_threads;
+
+pub type FqRecords = fastq::Records<io::BufReader<Box<dyn Read + Send + Sync>>>;
+
+fn open_fq(
+    fq_path: &str,
+    threads: usize,
+) -> Result<fastq::Reader<io::BufReader<Box<dyn Read + Send + Sync>>>, Error> {
+    let f = open_file_threads(fq_path, threads)?;
+    Ok(fastq::Reader::new(f))
+}
+
+/// Read name used to check the pairing, without the `/1` or `/2` suffix.
+/// CASAVA comments are not part of the record id.
+fn pair_name(id: &str) -> &str {
+    id.strip_suffix("/1")
+        .or_else(|| id.strip_suffix("/2"))
+        .unwrap_or(id)
+}
+
+/// Input file of a lane.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mate {
+    Fq1,
+    Fq2,
+    /// Index reads, 0 for I1.
+    Index(usize),
+}
+
+impl fmt::Display for Mate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Mate::Fq1 => write!(f, "fq1"),
+            Mate::Fq2 => write!(f, "fq2"),
+            Mate::Index(i) => write!(f, "i{}", i + 1),
+        }
+    }
+}
+
+/// Error of reading the pairs, with the input file to blame.
+pub type ReadError = (Mate, String);
+
+fn next_rec(recs: &mut FqRecords, index: u64, mate: Mate) -> Result<Option<Record>, ReadError> {
+    match recs.next() {
+        Some(Ok(rec)) => Ok(Some(rec)),
+        Some(Err(e)) => Err((
+            mate,
+            format!("Failed to parse record {} of {}: {}", index, mate, e),
+        )),
+        None => Ok(None),
+    }
+}
+
+/// Max number of records waiting for their mates in lenient mode.
+const MAX_PENDING: usize = 10000;
+
+/// Records of one read pair, `rec2` is `None` in SE mode.
+pub struct ReadPair {
+    pub rec1: Record,
+    pub rec2: Option<Record>,
+    /// Records of the index reads, empty without index reads.
+    pub index: Vec<Record>,
+}
+
+/// Record of reads 1 with its index reads.
+type Mate1 = (Record, Vec<Record>);
+
+/// Read records of fq1 and fq2 in lockstep, and check that they are paired.
+/// If `interleaved`, the mates are read from fq1 one after another.
+/// Index reads are read in lockstep with fq1.
+pub struct PairedReader {
+    recs1: FqRecords,
+    recs2: Option<FqRecords>,
+    index_recs: Vec<FqRecords>,
+    interleaved: bool,
+    /// Drop the unpaired records instead of raising an error.
+    lenient: bool,
+    index: u64,
+    pub dropped: u64,
+    done: bool,
+    // records not paired yet in lenient mode
+    pending1: VecDeque<Mate1>,
+    pending2: VecDeque<Record>,
+    ready: VecDeque<ReadPair>,
+}
+
+impl PairedReader {
+    pub fn new(
+        recs1: FqRecords,
+        recs2: Option<FqRecords>,
+        index_recs: Vec<FqRecords>,
+        interleaved: bool,
+        lenient: bool,
+    ) -> Self {
+        Self {
+            recs1,
+            recs2,
+            index_recs,
+            interleaved,
+            lenient,
+            index: 0,
+            dropped: 0,
+            done: false,
+            pending1: VecDeque::new(),
+            pending2: VecDeque::new(),
+            ready: VecDeque::new(),
+        }
+    }
+
+    pub fn next_pair(&mut self) -> Result<Option<ReadPair>, ReadError> {
+        let res = if self.lenient {
+            self.next_pair_lenient()
+        } else {
+            self.next_pair_strict()
+        };
+        if res.is_err() {
+            self.done = true;
+        }
+        res
+    }
+
+    fn next_pair_strict(&mut self) -> Result<Option<ReadPair>, ReadError> {
+        if self.done {
+            return Ok(None);
+        }
+        self.index += 1;
+        match self.read_pair()? {
+            (Some((rec1, index)), Some(rec2)) => {
+                if pair_name(rec1.id()) == pair_name(rec2.id()) {
+                    let rec2 = Some(rec2);
+                    return Ok(Some(ReadPair { rec1, rec2, index }));
+                }
+                let msg = format!(
+                    "Read names of fq1 and fq2 not match at record {}: {} != {}",
+                    self.index,
+                    rec1.id(),
+                    rec2.id()
+                );
+                Err((Mate::Fq2, msg))
+            }
+            (Some((rec1, index)), None) if self.is_se() => Ok(Some(ReadPair {
+                rec1,
+                rec2: None,
+                index,
+            })),
+            (None, None) => {
+                self.done = true;
+                Ok(None)
+            }
+            (_, _) if self.interleaved => {
+                let msg = format!(
+                    "Interleaved fastq has odd number of records, record {} has no mate",
+                    self.index * 2 - 1
+                );
+                Err((Mate::Fq1, msg))
+            }
+            (rec1, _) => {
+                // the shorter file is likely truncated
+                let (longer, shorter) = if rec1.is_some() {
+                    (Mate::Fq1, Mate::Fq2)
+                } else {
+                    (Mate::Fq2, Mate::Fq1)
+                };
+                let msg = format!(
+                    "{} has more records than {}, {} ends at record {}",
+                    longer,
+                    shorter,
+                    shorter,
+                    self.index - 1
+                );
+                Err((shorter, msg))
+            }
+        }
+    }
+
+    /// Pair the records by name within a window, drop the records without mate.
+    fn next_pair_lenient(&mut self) -> Result<Option<ReadPair>, ReadError> {
+        loop {
+            if let Some(pair) = self.ready.pop_front() {
+                return Ok(Some(pair));
+            }
+            if self.done {
+                return Ok(None);
+            }
+            self.index += 1;
+            match self.read_pair()? {
+                (Some((rec1, index)), None) if self.is_se() => {
+                    let rec2 = None;
+                    return Ok(Some(ReadPair { rec1, rec2, index }));
+                }
+                (None, None) => {
+                    self.done = true;
+                    self.dropped += (self.pending1.len() + self.pending2.len()) as u64;
+                    self.pending1.clear();
+                    self.pending2.clear();
+                }
+                (Some((rec1, index)), Some(rec2))
+                    if self.pending1.is_empty()
+                        && self.pending2.is_empty()
+                        && pair_name(rec1.id()) == pair_name(rec2.id()) =>
+                {
+                    let rec2 = Some(rec2);
+                    return Ok(Some(ReadPair { rec1, rec2, index }));
+                }
+                (mate1, rec2) => {
+                    if let Some(mate1) = mate1 {
+                        self.pending1.push_back(mate1);
+                        self.match_pending(true);
+                    }
+                    if let Some(rec2) = rec2 {
+                        self.pending2.push_back(rec2);
+                        self.match_pending(false);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Search the mate of the newest pending record. Records older than
+    /// a matched pair will never be paired, so they are dropped.
+    fn match_pending(&mut self, is_r1: bool) {
+        let (len_new, name) = if is_r1 {
+            let (rec, _) = self.pending1.back().unwrap();
+            (self.pending1.len(), pair_name(rec.id()).to_string())
+        } else {
+            let rec = self.pending2.back().unwrap();
+            (self.pending2.len(), pair_name(rec.id()).to_string())
+        };
+        let pos = if is_r1 {
+            self.pending2.iter().position(|r| pair_name(r.id()) == name)
+        } else {
+            self.pending1
+                .iter()
+                .position(|(r, _)| pair_name(r.id()) == name)
+        };
+        if let Some(pos) = pos {
+            self.dropped += (len_new - 1 + pos) as u64;
+            let (mate1, rec2) = if is_r1 {
+                let mate1 = self.pending1.pop_back().unwrap();
+                self.pending1.clear();
+                self.pending2.drain(..pos);
+                (mate1, self.pending2.pop_front().unwrap())
+            } else {
+                let rec2 = self.pending2.pop_back().unwrap();
+                self.pending2.clear();
+                self.pending1.drain(..pos);
+                (self.pending1.pop_front().unwrap(), rec2)
+            };
+            let (rec1, index) = mate1;
+            let rec2 = Some(rec2);
+            self.ready.push_back(ReadPair { rec1, rec2, index });
+        } else if len_new > MAX_PENDING {
+            if is_r1 {
+                self.pending1.pop_front();
+            } else {
+                self.pending2.pop_front();
+            }
+            self.dropped += 1;
+        }
+    }
+
+    fn is_se(&self) -> bool {
+        self.recs2.is_none() && !self.interleaved
+    }
+
+    fn read_pair(&mut self) -> Result<(Option<Mate1>, Option<Record>), ReadError> {
+        let rec1 = next_rec(&mut self.recs1, self.index, Mate::Fq1)?;
+        let index = self.read_index(rec1.as_ref())?;
+        let rec2 = match &mut self.recs2 {
+            Some(recs2) => next_rec(recs2, self.index, Mate::Fq2)?,
+            None if self.interleaved && rec1.is_some() => {
+                next_rec(&mut self.recs1, self.index, Mate::Fq1)?
+            }
+            None => None,
+        };
+        Ok((rec1.map(|r| (r, index)), rec2))
+    }
+
+    /// Read the index reads of `rec1`, they must be in the same order as fq1.
+    fn read_index(&mut self, rec1: Option<&Record>) -> Result<Vec<Record>, ReadError> {
+        let mut index = Vec::with_capacity(self.index_recs.len());
+        for (i, recs) in self.index_recs.iter_mut().enumerate() {
+            let mate = Mate::Index(i);
+            match (next_rec(recs, self.index, mate)?, rec1) {
+                (Some(rec), Some(rec1)) => {
+                    if pair_name(rec.id()) != pair_name(rec1.id()) {
+                        let msg = format!(
+                            "Read names of fq1 and {} not match at record {}: {} != {}",
+                            mate,
+                            self.index,
+                            rec1.id(),
+                            rec.id()
+                        );
+                        return Err((mate, msg));
+                    }
+                    index.push(rec);
+                }
+                (None, None) => {}
+                (Some(_), None) => {
+                    let msg = format!(
+                        "{} has more records than fq1, fq1 ends at record {}",
+                        mate,
+                        self.index - 1
+                    );
+                    return Err((Mate::Fq1, msg));
+                }
+                (None, Some(_)) => {
+                    let msg = format!(
+                        "fq1 has more records than {}, {} ends at record {}",
+                        mate,
+                        mate,
+                        self.index - 1
+                    );
+                    return Err((mate, msg));
+                }
+            }
+        }
+        Ok(index)
+    }
+}
+
+/// Input files of one lane.
+#[derive(Clone, Debug)]
+pub struct LaneInput {
+    pub fq1: String,
+    /// `None` in SE or interleaved mode.
+    pub fq2: Option<String>,
+    /// Index reads (I1, I2), in the same order as fq1.
+    pub index: Vec<String>,
+}
+
+/// Read the pairs of all lanes one after another, with the index of lane.
+pub struct LanesReader {
+    inputs: Vec<LaneInput>,
+    interleaved: bool,
+    lenient: bool,
+    /// Threads for decompressing each BGZF input.
+    threads: usize,
+    lane: usize,
+    reader: Option<PairedReader>,
+    /// Records dropped in each lane, in lenient mode.
+    pub dropped: Vec<u64>,
+}
+
+impl LanesReader {
+    pub fn new(inputs: Vec<LaneInput>, interleaved: bool, lenient: bool, threads: usize) -> Self {
+        let n_lanes = inputs.len();
+        Self {
+            inputs,
+            interleaved,
+            lenient,
+            threads,
+            lane: 0,
+            reader: None,
+            dropped: vec![0; n_lanes],
+        }
+    }
+
+    pub fn next_pair(&mut self) -> Result<Option<(usize, ReadPair)>, Error> {
+        while self.lane < self.inputs.len() {
+            if self.reader.is_none() {
+                let input = &self.inputs[self.lane];
+                info!(
+                    "Reading lane {}: {} {:?} {:?}",
+                    self.lane, input.fq1, input.fq2, input.index
+                );
+                let recs1 = open_fq(&input.fq1, self.threads)?.records();
+                let recs2 = match &input.fq2 {
+                    Some(p) => Some(open_fq(p, self.threads)?.records()),
+                    None => None,
+                };
+                let mut index_recs = vec![];
+                for p in &input.index {
+                    index_recs.push(open_fq(p, self.threads)?.records());
+                }
+                let reader =
+                    PairedReader::new(recs1, recs2, index_recs, self.interleaved, self.lenient);
+                self.reader = Some(reader);
+            }
+            let reader = self.reader.as_mut().unwrap();
+            match reader.next_pair() {
+                Ok(Some(pair)) => return Ok(Some((self.lane, pair))),
+                Ok(None) => {
+                    self.dropped[self.lane] = reader.dropped;
+                    self.reader = None;
+                    self.lane += 1;
+                }
+                Err((mate, msg)) => {
+                    let input = &self.inputs[self.lane];
+                    let path = match mate {
+                        Mate::Fq1 => &input.fq1,
+                        Mate::Fq2 => input.fq2.as_ref().unwrap(),
+                        Mate::Index(i) => &input.index[i],
+                    };
+                    let err = Error::format(path, None, msg);
+                    self.reader = None;
+                    self.lane = self.inputs.len();
+                    return Err(err);
+                }
+            }
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn records(names: &[&str]) -> FqRecords {
+        let text: String = names
+            .iter()
+            .map(|n| format!("@{}\nACGT\n+\nIIII\n", n))
+            .collect();
+        let f = Box::new(io::Cursor::new(text.into_bytes())) as Box<dyn Read + Send + Sync>;
+        fastq::Reader::new(f).records()
+    }
+
+    fn reader(names1: &[&str], names2: Option<&[&str]>, lenient: bool) -> PairedReader {
+        let interleaved = names2.is_none();
+        PairedReader::new(
+            records(names1),
+            names2.map(records),
+            vec![],
+            interleaved,
+            lenient,
+        )
+    }
+
+    /// Names of the pairs read, checked to be paired.
+    fn read_all(reader: &mut PairedReader) -> Result<Vec<String>, ReadError> {
+        let mut names = vec![];
+        while let Some(pair) = reader.next_pair()? {
+            let name = pair_name(pair.rec1.id()).to_string();
+            assert_eq!(pair_name(pair.rec2.unwrap().id()), name);
+            names.push(name);
+        }
+        Ok(names)
+    }
+
+    #[test]
+    fn test_missing_record() {
+        let full = ["r1/1", "r2/1", "r3/1", "r4/1", "r5/1"];
+        let missing = ["r1/2", "r2/2", "r4/2", "r5/2"];
+        let mut r = reader(&full, Some(&missing), false);
+        assert_eq!(read_all(&mut r).unwrap_err().0, Mate::Fq2);
+
+        // missing in fq2
+        let mut r = reader(&full, Some(&missing), true);
+        assert_eq!(read_all(&mut r).unwrap(), ["r1", "r2", "r4", "r5"]);
+        assert_eq!(r.dropped, 1);
+        // missing in fq1
+        let mut r = reader(&missing, Some(&full), true);
+        assert_eq!(read_all(&mut r).unwrap(), ["r1", "r2", "r4", "r5"]);
+        assert_eq!(r.dropped, 1);
+    }
+
+    #[test]
+    fn test_out_of_order() {
+        let names1 = ["r1", "r2", "r3", "r4"];
+        let names2 = ["r1", "r3", "r2", "r4"];
+        let mut r = reader(&names1, Some(&names2), false);
+        assert_eq!(read_all(&mut r).unwrap_err().0, Mate::Fq2);
+
+        // r2 is older than the matched r3 in fq1, both copies of it are dropped
+        let mut r = reader(&names1, Some(&names2), true);
+        assert_eq!(read_all(&mut r).unwrap(), ["r1", "r3", "r4"]);
+        assert_eq!(r.dropped, 2);
+    }
+
+    #[test]
+    fn test_odd_interleaved() {
+        let names = ["r1/1", "r1/2", "r2/1", "r2/2", "r3/1"];
+        let mut r = reader(&names, None, false);
+        let (mate, msg) = read_all(&mut r).unwrap_err();
+        assert_eq!(mate, Mate::Fq1);
+        assert!(msg.contains("record 5 has no mate"));
+
+        let mut r = reader(&names, None, true);
+        assert_eq!(read_all(&mut r).unwrap(), ["r1", "r2"]);
+        assert_eq!(r.dropped, 1);
+    }
+
+    #[test]
+    fn test_max_pending() {
+        // the mate of m is beyond the window, evicted before it is read
+        let n = MAX_PENDING + 1;
+        let names1: Vec<String> = (0..n).map(|i| format!("a{}", i)).collect();
+        let names2: Vec<String> = (0..n).map(|i| format!("b{}", i)).collect();
+        let names1: Vec<&str> = std::iter::once("m")
+            .chain(names1.iter().map(|s| s.as_str()))
+            .collect();
+        let names2: Vec<&str> = names2
+            .iter()
+            .map(|s| s.as_str())
+            .chain(std::iter::once("m"))
+            .collect();
+        let mut r = reader(&names1, Some(&names2), true);
+        assert!(read_all(&mut r).unwrap().is_empty());
+        assert_eq!(r.dropped, 2 * (n as u64 + 1));
+    }
+}
@@ -0,0 +1,424 @@
+//! Read structure of the library, one segment per line, from 5' to 3':
+//!
+//! ```text
+//! # type   length or sequence   read   strand
+//! pet      10-22:20
+//! spacer   GTCGGA
+//! barcode  8
+//! spacer   GCTAGC
+//! barcode  8
+//! spacer   TCCGAC
+//! pet      10-22:20
+//! barcode  8                    i1     -
+//! enzyme   T^TA^A
+//! ```
+//!
+//! Segments of the insert (read `r1`, the default) are listed as read 1 sees
+//! them: PET1, the linker composed of `spacer`, `barcode` and `umi` segments,
+//! then PET2. Length of PET is `min-max:cut`, PETs longer than `max` are cut
+//! to `cut` bases near the linker. Segments on index reads `i1`, `i2` are
+//! listed in the order of their position in the index read.
+//! `barcode` and `umi` segments with strand `-` are reported reverse complemented.
+
+use std::fmt;
+use std::fs;
+
+use bio::alphabets::dna::revcomp;
+
+use utils::error::{Error, Pos};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SegmentKind {
+    Pet,
+    Spacer,
+    Barcode,
+    Umi,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ReadKind {
+    /// The insert, as seen by read 1.
+    R1,
+    I1,
+    I2,
+}
+
+/// Range of the sequence in a read, with the strand it is reported in.
+#[derive(Clone, Debug)]
+pub struct Block {
+    pub start: usize,
+    pub end: usize,
+    pub reverse: bool,
+}
+
+impl Block {
+    pub fn extract(&self, seq: &[u8]) -> String {
+        let s = &seq[self.range(seq.len())];
+        let s = if self.reverse { revcomp(s) } else { s.to_vec() };
+        String::from_utf8(s).unwrap()
+    }
+
+    /// Qualities of the block, in the same strand as the sequence.
+    pub fn extract_qual(&self, qual: &[u8]) -> Vec<u8> {
+        let mut q = qual[self.range(qual.len())].to_vec();
+        if self.reverse {
+            q.reverse();
+        }
+        q
+    }
+
+    /// The block in the linker of length `len` read from the other side, like by read 2.
+    pub fn mirror(&self, len: usize) -> Self {
+        Self {
+            start: len - self.end,
+            end: len - self.start,
+            reverse: !self.reverse,
+        }
+    }
+
+    fn range(&self, len: usize) -> std::ops::Range<usize> {
+        let end = self.end.min(len);
+        self.start.min(end)..end
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Segment {
+    pub kind: SegmentKind,
+    pub read: ReadKind,
+    pub len: usize,
+    /// Fixed sequence of spacer.
+    pub seq: Option<String>,
+    /// min, max and cut length of PET.
+    pub pet_len: Option<(usize, usize, usize)>,
+    pub reverse: bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct ReadStructure {
+    pub segments: Vec<Segment>,
+    pub enzyme: String,
+}
+
+fn parse_pet_len(s: &str) -> Result<(usize, usize, usize), String> {
+    let err = || format!("PET length should be like 10-22:20, got {}", s);
+    let (range, cut) = s.split_once(':').ok_or_else(err)?;
+    let (min, max) = range.split_once('-').ok_or_else(err)?;
+    let min = min.parse().map_err(|_| err())?;
+    let max = max.parse().map_err(|_| err())?;
+    let cut = cut.parse().map_err(|_| err())?;
+    Ok((min, max, cut))
+}
+
+impl Segment {
+    fn from_line(line: &str) -> Result<Self, String> {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() < 2 || fields.len() > 4 {
+            return Err(format!("Segment should have 2 to 4 fields: {}", line));
+        }
+        let kind = match fields[0] {
+            "pet" => SegmentKind::Pet,
+            "spacer" | "linker" => SegmentKind::Spacer,
+            "barcode" => SegmentKind::Barcode,
+            "umi" => SegmentKind::Umi,
+            k => return Err(format!("Unknown segment type: {}", k)),
+        };
+        let read = match fields.get(2) {
+            None | Some(&"r1") => ReadKind::R1,
+            Some(&"i1") => ReadKind::I1,
+            Some(&"i2") => ReadKind::I2,
+            Some(r) => return Err(format!("Unknown read: {}", r)),
+        };
+        let reverse = match fields.get(3) {
+            None | Some(&"+") => false,
+            Some(&"-") => true,
+            Some(s) => return Err(format!("Strand should be + or -, got {}", s)),
+        };
+        let (len, seq, pet_len) = match kind {
+            SegmentKind::Pet => {
+                let pet_len = parse_pet_len(fields[1])?;
+                (0, None, Some(pet_len))
+            }
+            SegmentKind::Spacer => {
+                let seq = fields[1].to_uppercase();
+                if !seq.bytes().all(|b| b"ACGT".contains(&b)) {
+                    return Err(format!("Spacer should only contain ACGT: {}", seq));
+                }
+                (seq.len(), Some(seq), None)
+            }
+            _ => {
+                let len = fields[1]
+                    .parse()
+                    .map_err(|_| format!("Invalid length of {}: {}", fields[0], fields[1]))?;
+                (len, None, None)
+            }
+        };
+        if reverse && !matches!(kind, SegmentKind::Barcode | SegmentKind::Umi) {
+            return Err(format!("Only barcode and umi can be reversed: {}", line));
+        }
+        if read != ReadKind::R1 && kind == SegmentKind::Pet {
+            return Err(format!("PET should be in the insert: {}", line));
+        }
+        Ok(Self {
+            kind,
+            read,
+            len,
+            seq,
+            pet_len,
+            reverse,
+        })
+    }
+}
+
+impl ReadStructure {
+    pub fn from_file(path: &str) -> Result<Self, Error> {
+        let text = fs::read_to_string(path).map_err(|e| Error::io(path, e))?;
+        Self::parse(&text).map_err(|(n, msg)| Error::format(path, n.map(Pos::Line), msg))
+    }
+
+    /// Parse the text, the error is with the line number if it is in a line.
+    pub fn parse(text: &str) -> Result<Self, (Option<u64>, String)> {
+        let mut segments = vec![];
+        let mut enzyme = None;
+        for (n, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(e) = line.strip_prefix("enzyme") {
+                enzyme = Some(e.trim().to_string());
+                continue;
+            }
+            let segment = Segment::from_line(line).map_err(|e| (Some(n as u64 + 1), e))?;
+            segments.push(segment);
+        }
+        let enzyme = enzyme.ok_or((
+            None,
+            "Enzyme is not specified in read structure".to_string(),
+        ))?;
+        let structure = Self { segments, enzyme };
+        structure.validate().map_err(|(_, e)| (None, e))?;
+        Ok(structure)
+    }
+
+    /// Structure of the linker string with `N` runs as barcodes,
+    /// the error is of the argument it is caused by.
+    pub fn from_linker(
+        linker: &str,
+        enzyme: &str,
+        min_pet_len: usize,
+        max_pet_len: usize,
+        pet_cut_len: usize,
+    ) -> Result<Self, Error> {
+        let pet = format!("pet {}-{}:{}\n", min_pet_len, max_pet_len, pet_cut_len);
+        let mut segments = vec![];
+        let linker = linker.to_uppercase();
+        let mut start = 0;
+        let bytes = linker.as_bytes();
+        for i in 1..=bytes.len() {
+            if i == bytes.len() || (bytes[i] == b'N') != (bytes[i - 1] == b'N') {
+                let block = &linker[start..i];
+                let line = if bytes[start] == b'N' {
+                    format!("barcode {}", block.len())
+                } else {
+                    format!("spacer {}", block)
+                };
+                segments.push(Segment::from_line(&line).map_err(|e| Error::arg("linker", e))?);
+                start = i;
+            }
+        }
+        let pet = Segment::from_line(&pet).unwrap();
+        segments.insert(0, pet.clone());
+        segments.push(pet);
+        let structure = Self {
+            segments,
+            enzyme: enzyme.to_string(),
+        };
+        structure
+            .validate()
+            .map_err(|(name, e)| Error::arg(name, e))?;
+        Ok(structure)
+    }
+
+    /// The error is with the argument of `from_linker` it is caused by.
+    fn validate(&self) -> Result<(), (&'static str, String)> {
+        let pets: Vec<&Segment> = self
+            .segments
+            .iter()
+            .filter(|s| s.kind == SegmentKind::Pet)
+            .collect();
+        if pets.len() != 2 {
+            return Err((
+                "linker",
+                "Read structure should contain two PETs".to_string(),
+            ));
+        }
+        if pets[0].pet_len != pets[1].pet_len {
+            return Err(("linker", "Two PETs should have the same length".to_string()));
+        }
+        let (min, max, cut) = pets[0].pet_len.unwrap();
+        if min >= cut || cut >= max {
+            let msg = format!(
+                "PET length parameters should in this relationship: min < cut < max, got {}-{}:{}",
+                min, max, cut
+            );
+            return Err(("pet_cut_len", msg));
+        }
+        let insert: Vec<SegmentKind> = self.insert().map(|s| s.kind).collect();
+        if insert.first() != Some(&SegmentKind::Pet) || insert.last() != Some(&SegmentKind::Pet) {
+            return Err((
+                "linker",
+                "Linker should be between the two PETs".to_string(),
+            ));
+        }
+        if !self
+            .linker_segments()
+            .any(|s| s.kind == SegmentKind::Spacer)
+        {
+            return Err(("linker", "Linker should contain fixed sequence".to_string()));
+        }
+        if self.enzyme.split('^').count() != 3 {
+            return Err(("enzyme", "Enzyme should contain two cut site.".to_string()));
+        }
+        Ok(())
+    }
+
+    fn insert(&self) -> impl Iterator<Item = &Segment> {
+        self.segments.iter().filter(|s| s.read == ReadKind::R1)
+    }
+
+    fn linker_segments(&self) -> impl Iterator<Item = &Segment> {
+        self.insert().filter(|s| s.kind != SegmentKind::Pet)
+    }
+
+    /// Linker sequence, barcodes and UMIs are marked with `N`.
+    pub fn linker(&self) -> String {
+        self.linker_segments()
+            .map(|s| match &s.seq {
+                Some(seq) => seq.clone(),
+                None => "N".repeat(s.len),
+            })
+            .collect()
+    }
+
+    /// min, max and cut length of PETs.
+    pub fn pet_len(&self) -> (usize, usize, usize) {
+        self.insert().find_map(|s| s.pet_len).unwrap()
+    }
+
+    /// Blocks of `kind` in the linker, relative to the linker start.
+    pub fn linker_blocks(&self, kind: SegmentKind) -> Vec<Block> {
+        let mut blocks = vec![];
+        let mut pos = 0;
+        for s in self.linker_segments() {
+            if s.kind == kind {
+                blocks.push(Block {
+                    start: pos,
+                    end: pos + s.len,
+                    reverse: s.reverse,
+                });
+            }
+            pos += s.len;
+        }
+        blocks
+    }
+
+    /// Blocks of `kind` in the index reads, with the index of the index read.
+    pub fn index_blocks(&self, kind: SegmentKind) -> Vec<(usize, Block)> {
+        let mut blocks = vec![];
+        for (i, read) in [ReadKind::I1, ReadKind::I2].iter().enumerate() {
+            let mut pos = 0;
+            for s in self.segments.iter().filter(|s| s.read == *read) {
+                if s.kind == kind {
+                    blocks.push((
+                        i,
+                        Block {
+                            start: pos,
+                            end: pos + s.len,
+                            reverse: s.reverse,
+                        },
+                    ));
+                }
+                pos += s.len;
+            }
+        }
+        blocks
+    }
+
+    /// Number of index reads the structure needs.
+    pub fn n_index_reads(&self) -> usize {
+        self.segments
+            .iter()
+            .map(|s| match s.read {
+                ReadKind::R1 => 0,
+                ReadKind::I1 => 1,
+                ReadKind::I2 => 2,
+            })
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+impl fmt::Display for ReadStructure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for s in &self.segments {
+            let value = match (&s.seq, s.pet_len) {
+                (Some(seq), _) => seq.clone(),
+                (None, Some((min, max, cut))) => format!("{}-{}:{}", min, max, cut),
+                _ => s.len.to_string(),
+            };
+            let read = match s.read {
+                ReadKind::R1 => "r1",
+                ReadKind::I1 => "i1",
+                ReadKind::I2 => "i2",
+            };
+            let strand = if s.reverse { "-" } else { "+" };
+            let kind = format!("{:?}", s.kind).to_lowercase();
+            writeln!(f, "{}\t{}\t{}\t{}", kind, value, read, strand)?;
+        }
+        writeln!(f, "enzyme\t{}", self.enzyme)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_linker() {
+        let s =
+            ReadStructure::from_linker("GTCGGANNNNGCTAGCNNNNTCCGAC", "T^TA^A", 10, 22, 20).unwrap();
+        assert_eq!(s.linker(), "GTCGGANNNNGCTAGCNNNNTCCGAC");
+        let pos: Vec<(usize, usize)> = s
+            .linker_blocks(SegmentKind::Barcode)
+            .iter()
+            .map(|b| (b.start, b.end))
+            .collect();
+        assert_eq!(pos, vec![(6, 10), (16, 20)]);
+        assert_eq!(s.pet_len(), (10, 22, 20));
+        let arg =
+            |linker, enzyme, cut| match ReadStructure::from_linker(linker, enzyme, 10, 22, cut)
+                .unwrap_err()
+            {
+                Error::Arg { name, .. } => name,
+                e => panic!("{}", e),
+            };
+        assert_eq!(arg("GTCGGANNNNTCCGAC", "T^TA^A", 22), "pet_cut_len");
+        assert_eq!(arg("GTCGGANNNNTCCGAC", "TTAA", 20), "enzyme");
+        assert_eq!(arg("NNNN", "T^TA^A", 20), "linker");
+        assert_eq!(arg("GTCGG-NNNN", "T^TA^A", 20), "linker");
+    }
+
+    #[test]
+    fn test_parse() {
+        let text = "pet 10-22:20\nspacer GTCG\numi 4\nbarcode 4\nspacer CGAC\n\
+                    pet 10-22:20\nbarcode 3 i1 -\nenzyme T^TA^A\n";
+        let s = ReadStructure::parse(text).unwrap();
+        assert_eq!(s.linker(), "GTCGNNNNNNNNCGAC");
+        assert_eq!(s.linker_blocks(SegmentKind::Umi)[0].start, 4);
+        let idx = s.index_blocks(SegmentKind::Barcode);
+        assert_eq!(idx[0].1.extract(b"AACG"), "GTT");
+        assert_eq!(s.n_index_reads(), 1);
+        assert!(ReadStructure::parse("pet 10-22:20\nenzyme T^TA^A\n").is_err());
+    }
+}
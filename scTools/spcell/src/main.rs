@@ -1,13 +1,11 @@
 use std::collections::hash_map::Entry;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fmt;
-use std::fs::File;
 use std::io::{self, prelude::*};
 use std::ops;
 use std::sync::mpsc;
 use std::sync::{Arc, Mutex};
 use std::thread;
-use std::time::Duration;
 
 extern crate bio;
 extern crate clap;
@@ -18,22 +16,37 @@ extern crate vpsearch;
 
 use bio::alphabets::dna::revcomp;
 use clap::{App, Arg};
-use log::info;
+use log::{info, warn};
 use regex::Regex;
 use strsim::hamming;
 
-use utils::{add_hashmap, open_file_buffered, read_lines};
+use utils::config::{self, check_range, Params};
+use utils::error::{exit_with, Error, Pos};
+use utils::manifest::{self, Manifest};
+use utils::output;
+use utils::{add_hashmap, open_file_buffered, open_file_threads};
 
-fn load_barcodes(barcodes_file: &str) -> Vec<String> {
+fn load_barcodes(barcodes_file: &str) -> Result<Vec<String>, Error> {
     let mut codes = vec![];
-    if let Ok(lines) = read_lines(barcodes_file) {
-        for line in lines {
-            let line = line.unwrap();
-            let code = line.trim_end().to_string();
-            codes.push(code);
+    for line in open_file_buffered(barcodes_file)?.lines() {
+        let line = line.map_err(|e| Error::io(barcodes_file, e))?;
+        let code = line.trim_end();
+        if !code.is_empty() {
+            codes.push(code.to_string());
         }
     }
-    codes
+    if codes.is_empty() {
+        return Err(Error::Input(format!(
+            "Barcodes whitelist {} is empty.",
+            barcodes_file
+        )));
+    }
+    Ok(codes)
+}
+
+/// Hamming distance, the max value for the codes of different lengths.
+fn distance(a: &str, b: &str) -> usize {
+    hamming(a, b).unwrap_or(usize::MAX)
 }
 
 struct BarcodeSearch {
@@ -43,51 +56,108 @@ struct BarcodeSearch {
 
 impl BarcodeSearch {
     fn new(codes: Vec<String>, dist_thresh: usize) -> Self {
-        Self {
-            codes: codes,
-            dist_thresh: dist_thresh,
-        }
+        Self { codes, dist_thresh }
     }
 
-    fn search(&self, code: &String) -> Option<(&String, usize)> {
+    fn search(&self, code: &str) -> Option<(&String, usize)> {
         let mut min_dist = code.len();
         let mut index: usize = 0;
         for (i, c) in self.codes.iter().enumerate() {
-            let dist = hamming(c, code).unwrap();
+            let dist = distance(c, code);
             if dist < min_dist {
                 min_dist = dist;
                 index = i;
             }
         }
-        let res = if min_dist > self.dist_thresh {
+        if min_dist > self.dist_thresh {
             None
         } else {
             Some((&self.codes[index], min_dist))
-        };
-        res
+        }
     }
 }
 
 struct PairRec {
+    /// Sequences of the index reads, part of the cell barcode.
+    index: Option<String>,
+    umi: Option<String>,
     code_r1_l: String,
     code_r2_l: String,
     code_r1_r: String,
     code_r2_r: String,
 }
 
+/// Barcodes in the read ID, like `/ACGT-ACGT-ACGT-ACGT`, with the index reads
+/// `/AC+GT_ACGT-...` before them and the UMI `:TTGG` after them.
+fn codes_regex() -> Regex {
+    Regex::new(r".*/(?:([ACGTN+]+)_)?([ACGTN]+)-([ACGTN]+)-([ACGTN]+)-([ACGTN]+)(?::([ACGTN]+))?\t")
+        .unwrap()
+}
+
 impl PairRec {
-    fn from_line(line: &str, re_codes: &Regex) -> Self {
+    fn from_line(line: &str, re_codes: &Regex) -> Result<Self, String> {
         let line = line.trim_end();
-        let codes = re_codes.captures(line).unwrap();
-        let r1_r_rc = String::from_utf8(revcomp(codes[3].as_bytes().to_vec())).unwrap();
-        let r2_r_rc = String::from_utf8(revcomp(codes[4].as_bytes().to_vec())).unwrap();
-        Self {
-            code_r1_l: codes[1].to_string(),
-            code_r2_l: codes[2].to_string(),
+        let codes = re_codes
+            .captures(line)
+            .ok_or_else(|| "no barcodes in read ID".to_string())?;
+        let r1_r_rc = String::from_utf8(revcomp(codes[4].as_bytes().to_vec())).unwrap();
+        let r2_r_rc = String::from_utf8(revcomp(codes[5].as_bytes().to_vec())).unwrap();
+        Ok(Self {
+            index: codes.get(1).map(|m| m.as_str().to_string()),
+            umi: codes.get(6).map(|m| m.as_str().to_string()),
+            code_r1_l: codes[2].to_string(),
+            code_r2_l: codes[3].to_string(),
             code_r1_r: r1_r_rc,
             code_r2_r: r2_r_rc,
+        })
+    }
+}
+
+/// Remove the PCR duplicates, pairs with same cell, position and similar UMI.
+/// The pairs file is sorted by position, so the duplicates are adjacent and
+/// only the UMIs of the current position are kept.
+struct Deduper {
+    /// chr1 pos1 chr2 pos2 of the last pair.
+    pos: String,
+    /// UMIs of the current position, by cell and strands.
+    umis: HashMap<String, Vec<String>>,
+    max_dist: usize,
+}
+
+impl Deduper {
+    fn new(max_dist: usize) -> Self {
+        Self {
+            pos: String::new(),
+            umis: HashMap::new(),
+            max_dist,
         }
     }
+
+    /// Check if the pair is a duplicate of the seen pairs, cluster the UMIs greedily:
+    /// UMI within `max_dist` to a seen UMI of the same position belongs to its cluster,
+    /// the first pair of the cluster is kept.
+    fn is_dup(&mut self, code: &str, line: &str, umi: &str) -> bool {
+        // chr1 pos1 chr2 pos2 strand1 strand2
+        let fields: Vec<&str> = line.split('\t').skip(1).take(6).collect();
+        let (pos, strands) = fields.split_at(fields.len().min(4));
+        let pos = pos.join("\t");
+        if pos != self.pos {
+            // the input moves past the last position
+            self.umis.clear();
+            self.pos = pos;
+        }
+        let key = format!("{}\t{}", code, strands.join("\t"));
+        let umis = self.umis.entry(key).or_default();
+        let max_dist = self.max_dist;
+        if umis.iter().any(|u| match hamming(u, umi) {
+            Ok(d) => d <= max_dist,
+            Err(_) => false,
+        }) {
+            return true;
+        }
+        umis.push(umi.to_string());
+        false
+    }
 }
 
 #[derive(Clone)]
@@ -95,6 +165,7 @@ struct Counter {
     b1b2_not_match: u64,
     r1r2_not_match: u64,
     barcode_not_found: u64,
+    duplicate: u64,
     valid: u64,
     total: u64,
     barcode_cnts: HashMap<String, u64>,
@@ -106,6 +177,7 @@ impl Counter {
             b1b2_not_match: 0,
             r1r2_not_match: 0,
             barcode_not_found: 0,
+            duplicate: 0,
             valid: 0,
             total: 0,
             barcode_cnts: HashMap::new(),
@@ -121,6 +193,7 @@ impl ops::Add<Counter> for Counter {
             b1b2_not_match: self.b1b2_not_match + _rhs.b1b2_not_match,
             r1r2_not_match: self.r1r2_not_match + _rhs.r1r2_not_match,
             barcode_not_found: self.barcode_not_found + _rhs.barcode_not_found,
+            duplicate: self.duplicate + _rhs.duplicate,
             valid: self.valid + _rhs.valid,
             total: self.total + _rhs.total,
             barcode_cnts: add_hashmap(self.barcode_cnts, _rhs.barcode_cnts),
@@ -132,7 +205,7 @@ impl fmt::Display for Counter {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let ratio = |c| {
             if self.total == 0 {
-                return format!("0%");
+                return "0%".to_string();
             }
             format!("{:.2}%", ((c * 100) as f64) / (self.total as f64))
         };
@@ -142,6 +215,7 @@ impl fmt::Display for Counter {
                b1b2_not_match\t{}\t{}\n\
                r1r2_not_match\t{}\t{}\n\
                barcode_not_found\t{}\t{}\n\
+               duplicate\t{}\t{}\n\
                total reads\t{}\n\n",
             self.valid,
             ratio(self.valid),
@@ -151,6 +225,8 @@ impl fmt::Display for Counter {
             ratio(self.r1r2_not_match),
             self.barcode_not_found,
             ratio(self.barcode_not_found),
+            self.duplicate,
+            ratio(self.duplicate),
             self.total,
         );
         msg.push_str("Barcode counts:\n");
@@ -172,14 +248,14 @@ fn locate_barcode<'a>(
     counter: &mut Counter,
 ) -> Option<String> {
     counter.total += 1;
-    if hamming(&pair_rec.code_r1_l, &pair_rec.code_r1_r).unwrap() > max_diff_b1b2
-        || hamming(&pair_rec.code_r2_l, &pair_rec.code_r2_r).unwrap() > max_diff_b1b2
+    if distance(&pair_rec.code_r1_l, &pair_rec.code_r1_r) > max_diff_b1b2
+        || distance(&pair_rec.code_r2_l, &pair_rec.code_r2_r) > max_diff_b1b2
     {
         counter.b1b2_not_match += 1;
         return None;
     }
-    if hamming(&pair_rec.code_r1_l, &pair_rec.code_r2_l).unwrap() > max_diff_r1r2
-        || hamming(&pair_rec.code_r1_r, &pair_rec.code_r2_r).unwrap() > max_diff_r1r2
+    if distance(&pair_rec.code_r1_l, &pair_rec.code_r2_l) > max_diff_r1r2
+        || distance(&pair_rec.code_r1_r, &pair_rec.code_r2_r) > max_diff_r1r2
     {
         counter.r1r2_not_match += 1;
         return None;
@@ -204,32 +280,58 @@ fn locate_barcode<'a>(
             res_vec.push((barcode, dist))
         }
     }
-    if res_vec.len() > 0 {
+    if !res_vec.is_empty() {
         counter.valid += 1;
         res_vec.sort_by(|t1, t2| t1.1.partial_cmp(&t2.1).unwrap());
-        let code = res_vec[0].0.clone();
+        let code = match &pair_rec.index {
+            Some(index) => format!("{}_{}", index, res_vec[0].0),
+            None => res_vec[0].0.clone(),
+        };
         *counter.barcode_cnts.entry(code.clone()).or_insert(0) += 1;
-        return Some(code);
+        Some(code)
     } else {
         counter.barcode_not_found += 1;
-        return None;
+        None
     }
 }
 
 fn main() {
     simple_logger::SimpleLogger::new().init().unwrap();
+    if let Err(e) = run() {
+        exit_with(&e);
+    }
+}
 
+fn run() -> Result<(), Error> {
+    let mut manifest = Manifest::new("spcell", env!("CARGO_PKG_VERSION"));
+    let presets_help = config::presets_help();
     let matches = App::new("Split pairs file by barcodes.")
         .arg(
             Arg::with_name("pairs_file")
-                .required(true)
-                .help("Path of input pairs file."),
+                .required_unless("config")
+                .help("Path of input pairs file, '-' for stdin."),
         )
         .arg(
             Arg::with_name("barcodes_file")
-                .required(true)
+                .required_unless("config")
                 .help("Path to the txt file which store all barcodes."),
         )
+        .arg(
+            Arg::with_name("config")
+                .long("config")
+                .takes_value(true)
+                .help(
+                    "TOML or YAML file of the options, shared with expet. \
+                     Options of the command line override it.",
+                ),
+        )
+        .arg(
+            Arg::with_name("preset")
+                .long("preset")
+                .takes_value(true)
+                .possible_values(&config::preset_names())
+                .help(&presets_help),
+        )
         .arg(
             Arg::with_name("dist_thresh")
                 .short("d")
@@ -254,14 +356,36 @@ fn main() {
                 .takes_value(true)
                 .help("Threshould of edit distance between R1 barcode to R2 barcode."),
         )
+        .arg(
+            Arg::with_name("dedup")
+                .long("dedup")
+                .takes_value(false)
+                .help(
+                    "Remove PCR duplicates, pairs with same cell, position and UMI. \
+                     Only position is compared if reads have no UMI. \
+                     The pairs file should be sorted by position.",
+                ),
+        )
+        .arg(
+            Arg::with_name("umi_dist")
+                .long("umi_dist")
+                .takes_value(true)
+                .help("Max hamming distance between UMIs of duplicates, default 1."),
+        )
         .arg(
             Arg::with_name("output_prefix")
                 .short("o")
                 .long("output_prefix")
-                .required(true)
+                .required_unless("config")
                 .takes_value(true)
                 .help("Prefix of output files."),
         )
+        .arg(
+            Arg::with_name("force")
+                .long("force")
+                .takes_value(false)
+                .help("Overwrite the existing output files."),
+        )
         .arg(
             Arg::with_name("threads")
                 .short("t")
@@ -273,35 +397,66 @@ fn main() {
             Arg::with_name("wait_timeout")
                 .long("wait_timeout")
                 .takes_value(true)
-                .help("Wait time for end channel timeout."),
+                .hidden(true)
+                .help("Deprecated and ignored, outputs are written until all reads are processed."),
         )
         .get_matches();
 
-    let pairs_path = matches.value_of("pairs_file").unwrap();
-    let barcodes_path = matches.value_of("barcodes_file").unwrap();
-    let dist_thresh = matches.value_of("dist_thresh").unwrap_or("1");
-    let dist_thresh: usize = dist_thresh.parse().unwrap();
-    let max_diff_b1b2 = matches.value_of("max_diff_b1b2").unwrap_or("2");
-    let max_diff_b1b2: usize = max_diff_b1b2.parse().unwrap();
-    let max_diff_r1r2 = matches.value_of("max_diff_r1r2").unwrap_or("4");
-    let max_diff_r1r2: usize = max_diff_r1r2.parse().unwrap();
-    let output_prefix = matches.value_of("output_prefix").unwrap();
-    let threads = matches.value_of("threads").unwrap_or("1");
-    let threads: u8 = threads.parse().unwrap();
-    let wait_t = matches.value_of("wait_timeout").unwrap_or("2000");
-    let wait_t: u64 = wait_t.parse().unwrap();
+    let params = Params::new("spcell", &matches)?;
+    let pairs_path = params.required("pairs_file")?;
+    let barcodes_path = params.required("barcodes_file")?;
+    let dist_thresh: usize = params.get_or("dist_thresh", 1)?;
+    let max_diff_b1b2: usize = params.get_or("max_diff_b1b2", 2)?;
+    let max_diff_r1r2: usize = params.get_or("max_diff_r1r2", 4)?;
+    let output_prefix = params.required("output_prefix")?;
+    let force = params.is_present("force")?;
+    let dedup = params.is_present("dedup")?;
+    // distance of UMIs is only used by the deduplication
+    let umi_dist: usize = if dedup {
+        params.get_or("umi_dist", 1)?
+    } else {
+        1
+    };
+    let threads: u8 = params.get_or("threads", 1)?;
+    let threads = check_range("threads", threads, 1, u8::MAX)?;
+    if params.value_of("wait_timeout")?.is_some() {
+        warn!("--wait_timeout is deprecated and ignored, outputs are written until all reads are processed.");
+    }
+    params.check_unused()?;
 
     info!(
         "pairs_file: {} barcodes_file: {}\n \
            dist_thresh: {}, max_diff_b1b2: {}, max_diff_r1r2: {}\n \
-           threads: {} wait_timeout: {}",
-        pairs_path, barcodes_path, dist_thresh, max_diff_b1b2, max_diff_r1r2, threads, wait_t
+           threads: {}",
+        pairs_path, barcodes_path, dist_thresh, max_diff_b1b2, max_diff_r1r2, threads
     );
 
-    let barcodes = load_barcodes(barcodes_path);
+    manifest.input(pairs_path);
+    manifest.input(barcodes_path);
+    if let Some(path) = matches.value_of("config") {
+        manifest.input(path);
+    }
+    manifest.threads = threads as usize;
+    manifest.param("config", matches.value_of("config"));
+    manifest.param("preset", params.preset());
+    manifest.param("dist_thresh", dist_thresh);
+    manifest.param("max_diff_b1b2", max_diff_b1b2);
+    manifest.param("max_diff_r1r2", max_diff_r1r2);
+    manifest.param("dedup", dedup);
+    manifest.param("umi_dist", umi_dist);
+    manifest.param("output_prefix", output_prefix);
+
+    output::handle_signals();
+    let counter_res_path = format!("{}.count.txt", output_prefix);
+    let mut counter_res_file = output::create(&counter_res_path, force)?;
+    manifest::start(&format!("{}.run.json", output_prefix), force, manifest)?;
+
+    let barcodes = load_barcodes(barcodes_path)?;
     let barcode_search = BarcodeSearch::new(barcodes, dist_thresh);
-    let re_codes: Regex = Regex::new(r".*/(.{8})-(.{8})-(.{8})-(.{8})\t").unwrap();
-    let lines = open_file_buffered(pairs_path).lines();
+    let re_codes = codes_regex();
+    let lines = io::BufReader::new(open_file_threads(pairs_path, threads as usize)?)
+        .lines()
+        .enumerate();
 
     // variables shared by threads
     let barcode_search = Arc::new(barcode_search);
@@ -321,20 +476,26 @@ fn main() {
         let barcode_search = Arc::clone(&barcode_search);
         let counters = Arc::clone(&counters);
         let tx1 = mpsc::Sender::clone(&tx);
-        let handle = thread::spawn(move || {
+        let pairs_path = pairs_path.to_string();
+        let handle = thread::spawn(move || -> Result<(), Error> {
             let mut search_cache = HashMap::new();
-            loop {
-                let line = {
+            while !output::is_interrupted() {
+                let (i, line) = {
                     let mut lines = lines.lock().unwrap();
                     match lines.next() {
-                        Some(line) => line.unwrap(),
+                        Some((i, line)) => (i, line.map_err(|e| Error::io(&pairs_path, e))?),
                         None => break,
                     }
                 };
-                if line.starts_with("#") {
+                if line.starts_with('#') {
+                    // sent to keep the order of lines
+                    if tx1.send((i, line, None, None)).is_err() {
+                        break;
+                    }
                     continue;
                 }
-                let rec = PairRec::from_line(&line, &re_codes);
+                let rec = PairRec::from_line(&line, &re_codes)
+                    .map_err(|e| Error::format(&pairs_path, Some(Pos::Line(i as u64 + 1)), e))?;
                 let b = {
                     let mut counter = counters[t_id as usize].lock().unwrap();
                     locate_barcode(
@@ -346,49 +507,133 @@ fn main() {
                         &mut counter,
                     )
                 };
-                tx1.send((line, b)).unwrap();
+                if tx1.send((i, line, b, rec.umi)).is_err() {
+                    // writer stopped by interruption
+                    break;
+                }
             }
+            Ok(())
         });
         handles.push(handle);
     }
+    // the channel is closed when all the workers are done
+    drop(tx);
 
     let mut code_to_file: HashMap<String, _> = HashMap::new();
+    let mut deduper = Deduper::new(umi_dist);
+    let mut n_dup = 0;
 
-    let open_out_file = |code| {
-        let file_name = format!("{}.{}.pairs", output_prefix, code);
+    let out_path = |code: &str| format!("{}.{}.pairs", output_prefix, code);
+    let open_out_file = |code: &str| -> Result<_, Error> {
+        let file_name = out_path(code);
         info!("open {}", file_name);
-        io::BufWriter::new(File::create(file_name).unwrap())
+        Ok(io::BufWriter::new(output::create(&file_name, force)?))
     };
 
-    loop {
-        match rx.recv_timeout(Duration::from_millis(wait_t)) {
-            Ok((line, b)) => {
-                if let Some(code) = b {
-                    if let None = code_to_file.get(&code) {
-                        let f_ = open_out_file(code.clone());
-                        code_to_file.insert(code.clone(), f_);
-                    };
-                    let f = code_to_file.get_mut(&code).unwrap();
-                    write!(f, "{}\n", line).unwrap();
-                }
-            }
-            _ => {
-                info!("End split cell.");
-                break;
+    // lines are written in the input order, so the outputs and the kept pairs
+    // of duplicates are the same between runs
+    let mut pending = BTreeMap::new();
+    let mut next = 0;
+    for (i, line, b, umi) in rx {
+        if output::is_interrupted() {
+            break;
+        }
+        pending.insert(i, (line, b, umi));
+        while let Some((line, b, umi)) = pending.remove(&next) {
+            next += 1;
+            let code = match b {
+                Some(code) => code,
+                None => continue,
+            };
+            let umi = umi.unwrap_or_default();
+            if dedup && deduper.is_dup(&code, &line, &umi) {
+                n_dup += 1;
+                continue;
             }
+            if !code_to_file.contains_key(&code) {
+                let f_ = open_out_file(&code)?;
+                code_to_file.insert(code.clone(), f_);
+            };
+            let f = code_to_file.get_mut(&code).unwrap();
+            writeln!(f, "{}", line).map_err(|e| Error::io(&out_path(&code), e))?;
         }
     }
+    info!("End split cell.");
 
-    for handle in handles {
-        handle.join().unwrap();
+    for (code, f) in code_to_file.iter_mut() {
+        f.flush().map_err(|e| Error::io(&out_path(code), e))?;
     }
+    output::exit_if_interrupted();
+    // errors of the workers fail the run, and the outputs are removed
+    output::join_workers(handles)?;
 
     let mut counter = counters[0].lock().unwrap().clone();
     for i in 1..counters.len() {
         counter = counter + counters[i].lock().unwrap().clone();
     }
+    counter.duplicate = n_dup;
     info!("{}", counter);
-    let counter_res_path = format!("{}.count.txt", output_prefix);
-    let mut counter_res_file = File::create(counter_res_path).unwrap();
-    write!(counter_res_file, "{}", counter).unwrap();
+    write!(counter_res_file, "{}", counter).map_err(|e| Error::io(&counter_res_path, e))?;
+    drop(code_to_file);
+    output::commit()?;
+    manifest::finish(0, None);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pair_rec() {
+        let re = codes_regex();
+        let rec =
+            PairRec::from_line("r1/AACC-AACC-GGTT-GGTT\tchr1\t1\tchr2\t1\t+\t-", &re).unwrap();
+        assert_eq!((rec.index, rec.umi), (None, None));
+        assert_eq!(
+            (rec.code_r1_l.as_str(), rec.code_r2_l.as_str()),
+            ("AACC", "AACC")
+        );
+        assert_eq!(
+            (rec.code_r1_r.as_str(), rec.code_r2_r.as_str()),
+            ("AACC", "AACC")
+        );
+
+        let line = "r1/AC+GT_AACC-AACC-GGTT-GGTT:TTGA\tchr1\t1\tchr2\t1\t+\t-";
+        let rec = PairRec::from_line(line, &re).unwrap();
+        assert_eq!(rec.index.as_deref(), Some("AC+GT"));
+        assert_eq!(rec.umi.as_deref(), Some("TTGA"));
+        assert_eq!(rec.code_r1_l, "AACC");
+
+        assert!(PairRec::from_line("r1\tchr1\t1\tchr2\t1\t+\t-", &re).is_err());
+        assert!(PairRec::from_line("r1/AACC-AACC\tchr1\t1\tchr2\t1\t+\t-", &re).is_err());
+    }
+
+    #[test]
+    fn test_deduper() {
+        let mut deduper = Deduper::new(1);
+        let pos1 = "r\tchr1\t100\tchr2\t200\t+\t-";
+        let pos2 = "r\tchr1\t100\tchr2\t300\t+\t-";
+        assert!(!deduper.is_dup("A", pos1, "AAAA"));
+        assert!(deduper.is_dup("A", pos1, "AAAA"));
+        // UMI within the hamming distance 1 joins the cluster
+        assert!(deduper.is_dup("A", pos1, "AAAT"));
+        assert!(!deduper.is_dup("A", pos1, "AATT"));
+        assert!(deduper.is_dup("A", pos1, "ATTT"));
+        assert!(!deduper.is_dup("A", pos1, "CCCC"));
+        // other cell or strands
+        assert!(!deduper.is_dup("B", pos1, "AAAA"));
+        assert!(!deduper.is_dup("A", "r\tchr1\t100\tchr2\t200\t-\t-", "AAAA"));
+        // the UMIs of the last position are removed
+        assert!(!deduper.is_dup("A", pos2, "AAAA"));
+        assert_eq!(deduper.umis.len(), 1);
+        assert!(!deduper.is_dup("A", pos1, "AAAA"));
+
+        // only the position without UMI
+        let mut deduper = Deduper::new(0);
+        assert!(!deduper.is_dup("A", pos1, ""));
+        assert!(deduper.is_dup("A", pos1, ""));
+        assert!(!deduper.is_dup("A", pos1, "AAAA"));
+        assert!(!deduper.is_dup("A", pos1, "AAAT"));
+    }
 }
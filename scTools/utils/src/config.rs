@@ -0,0 +1,431 @@
+//! Options of a run from the command line, a config file and the built-in presets
+//! of our protocols. Command line overrides the config file, which overrides the
+//! preset, and the values from all of them are parsed and checked the same way.
+//!
+//! The config file is TOML, or YAML if named `.yaml`/`.yml`. Top-level keys are
+//! options of both tools, the `expet` and `spcell` tables are options of each:
+//!
+//! ```toml
+//! preset = "scidlo-pe-msei"
+//! threads = 8
+//!
+//! [expet]
+//! fq1 = ["lib1_1.fq.gz"]
+//! fq2 = ["lib1_2.fq.gz"]
+//! output_prefix = "lib1"
+//!
+//! [spcell]
+//! barcodes_file = "barcodes.txt"
+//! ```
+
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fs;
+use std::str::FromStr;
+
+use clap::ArgMatches;
+use serde::Deserialize;
+
+use crate::error::{parse_arg, Error, Result};
+
+/// Tools with a table in the config file.
+const TOOLS: [&str; 2] = ["expet", "spcell"];
+
+/// Built-in presets of the protocols: name, description and the options in TOML.
+pub const PRESETS: &[(&str, &str, &str)] = &[
+    (
+        "scidlo-pe-msei",
+        "sciDLO Hi-C, paired-end reads, MseI",
+        r#"
+[expet]
+linker = "GTCGGANNNNNNNNGCTAGCNNNNNNNNTCCGAC"
+enzyme = "T^TA^A"
+split_barcode = true
+
+[spcell]
+dist_thresh = 1
+max_diff_b1b2 = 2
+max_diff_r1r2 = 4
+"#,
+    ),
+    (
+        "scidlo-se-msei",
+        "sciDLO Hi-C, single-end reads, MseI",
+        r#"
+[expet]
+linker = "GTCGGANNNNNNNNGCTAGCNNNNNNNNTCCGAC"
+enzyme = "T^TA^A"
+split_barcode = true
+adapter = "AGATCGGAAGAGC"
+
+[spcell]
+dist_thresh = 1
+max_diff_b1b2 = 2
+max_diff_r1r2 = 4
+"#,
+    ),
+    (
+        "insitu-dlo-pe",
+        "in situ DLO Hi-C, paired-end reads, MseI",
+        r#"
+[expet]
+linker = "GTCGGAGAACCAGTAGCTACTGGTTCTCCGAC"
+enzyme = "T^TA^A"
+"#,
+    ),
+    (
+        "insitu-dlo-se",
+        "in situ DLO Hi-C, single-end reads, MseI",
+        r#"
+[expet]
+linker = "GTCGGAGAACCAGTAGCTACTGGTTCTCCGAC"
+enzyme = "T^TA^A"
+adapter = "AGATCGGAAGAGC"
+"#,
+    ),
+];
+
+pub fn preset_names() -> Vec<&'static str> {
+    PRESETS.iter().map(|p| p.0).collect()
+}
+
+/// Help message of the presets, one line for each.
+pub fn presets_help() -> String {
+    let mut msg = "Built-in options of a protocol, overridden by the config and the command line:"
+        .to_string();
+    for (name, desc, _) in PRESETS {
+        msg.push_str(&format!("\n{}: {}", name, desc));
+    }
+    msg
+}
+
+/// Value in the config file, of either format.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Raw {
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Text(String),
+    List(Vec<Raw>),
+    Table(BTreeMap<String, Raw>),
+}
+
+impl Raw {
+    fn scalar(&self) -> Option<String> {
+        match self {
+            Raw::Int(v) => Some(v.to_string()),
+            Raw::Float(v) => Some(v.to_string()),
+            Raw::Text(v) => Some(v.clone()),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Flag(bool),
+    Text(String),
+    List(Vec<String>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Source {
+    Preset(String),
+    File(String),
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Source::Preset(name) => write!(f, "preset {}", name),
+            Source::File(path) => write!(f, "{}", path),
+        }
+    }
+}
+
+/// Options of `tool` in the config text, and the preset it is based on.
+fn parse_config(
+    text: &str,
+    yaml: bool,
+    tool: &str,
+    source: &Source,
+) -> Result<(Option<String>, BTreeMap<String, Value>)> {
+    let invalid = |msg: String| Error::format(&source.to_string(), None, msg);
+    let table: BTreeMap<String, Raw> = if yaml {
+        serde_yaml::from_str(text).map_err(|e| invalid(e.to_string()))?
+    } else {
+        toml::from_str(text).map_err(|e| invalid(e.to_string()))?
+    };
+    let mut preset = None;
+    let mut shared = vec![];
+    let mut own = vec![];
+    for (key, raw) in table {
+        match (key.as_str(), raw) {
+            ("preset", Raw::Text(name)) => preset = Some(name),
+            ("preset", _) => return Err(invalid("preset should be a name".to_string())),
+            (k, Raw::Table(t)) if k == tool => own.extend(t),
+            // options of the other tool
+            (k, Raw::Table(_)) if TOOLS.contains(&k) => {}
+            (_, raw) => shared.push((key, raw)),
+        }
+    }
+    let mut options = BTreeMap::new();
+    // options in the table of tool override the shared ones
+    for (key, raw) in shared.into_iter().chain(own) {
+        let value = match raw {
+            Raw::Bool(b) => Value::Flag(b),
+            Raw::List(items) => {
+                let items: Option<Vec<String>> = items.iter().map(Raw::scalar).collect();
+                let msg = format!("items of {} should be numbers or strings", key);
+                Value::List(items.ok_or_else(|| invalid(msg))?)
+            }
+            Raw::Table(_) => return Err(invalid(format!("{} should not be a table", key))),
+            raw => Value::Text(raw.scalar().unwrap()),
+        };
+        options.insert(key, value);
+    }
+    Ok((preset, options))
+}
+
+/// Options resolved from the command line, config file and preset.
+pub struct Params<'a> {
+    tool: &'static str,
+    matches: &'a ArgMatches<'a>,
+    preset: Option<String>,
+    values: BTreeMap<String, (Value, Source)>,
+    /// Options read by the tool, the others given are mistakes.
+    used: RefCell<BTreeSet<String>>,
+}
+
+impl<'a> Params<'a> {
+    /// Load the `--config` file and the preset of `--preset` or of the config.
+    pub fn new(tool: &'static str, matches: &'a ArgMatches<'a>) -> Result<Self> {
+        let config = match matches.value_of("config") {
+            Some(path) => {
+                let text = fs::read_to_string(path).map_err(|e| Error::io(path, e))?;
+                let yaml = path.ends_with(".yaml") || path.ends_with(".yml");
+                let source = Source::File(path.to_string());
+                let (preset, options) = parse_config(&text, yaml, tool, &source)?;
+                Some((source, preset, options))
+            }
+            None => None,
+        };
+        let preset = match matches.value_of("preset") {
+            Some(name) => Some(name.to_string()),
+            None => config.as_ref().and_then(|c| c.1.clone()),
+        };
+        let mut values = BTreeMap::new();
+        if let Some(name) = &preset {
+            let text = match PRESETS.iter().find(|p| p.0 == name) {
+                Some(p) => p.2,
+                None => {
+                    let msg = format!("unknown {}, one of {}", name, preset_names().join(", "));
+                    return Err(Error::arg("preset", msg));
+                }
+            };
+            let source = Source::Preset(name.clone());
+            let (_, options) = parse_config(text, false, tool, &source)?;
+            for (k, v) in options {
+                values.insert(k, (v, source.clone()));
+            }
+        }
+        if let Some((source, _, options)) = config {
+            for (k, v) in options {
+                values.insert(k, (v, source.clone()));
+            }
+        }
+        let used = ["config", "preset"].iter().map(|s| s.to_string()).collect();
+        Ok(Self {
+            tool,
+            matches,
+            preset,
+            values,
+            used: RefCell::new(used),
+        })
+    }
+
+    /// Name of the preset, by the command line or config.
+    pub fn preset(&self) -> Option<&str> {
+        self.preset.as_deref()
+    }
+
+    fn config_value(&self, name: &str) -> Option<&(Value, Source)> {
+        self.used.borrow_mut().insert(name.to_string());
+        self.values.get(name)
+    }
+
+    fn invalid(&self, source: &Source, msg: String) -> Error {
+        Error::format(&source.to_string(), None, msg)
+    }
+
+    pub fn value_of(&self, name: &str) -> Result<Option<&str>> {
+        if let Some(v) = self.matches.value_of(name) {
+            self.used.borrow_mut().insert(name.to_string());
+            return Ok(Some(v));
+        }
+        match self.config_value(name) {
+            None => Ok(None),
+            Some((Value::Text(v), _)) => Ok(Some(v)),
+            Some((_, source)) => Err(self.invalid(source, format!("{} should be a value", name))),
+        }
+    }
+
+    pub fn values_of(&self, name: &str) -> Result<Option<Vec<&str>>> {
+        if let Some(v) = self.matches.values_of(name) {
+            self.used.borrow_mut().insert(name.to_string());
+            return Ok(Some(v.collect()));
+        }
+        match self.config_value(name) {
+            None => Ok(None),
+            Some((Value::Text(v), _)) => Ok(Some(vec![v])),
+            Some((Value::List(v), _)) => Ok(Some(v.iter().map(|s| s.as_str()).collect())),
+            Some((_, source)) => Err(self.invalid(source, format!("{} should be a list", name))),
+        }
+    }
+
+    pub fn is_present(&self, name: &str) -> Result<bool> {
+        if self.matches.is_present(name) {
+            self.used.borrow_mut().insert(name.to_string());
+            return Ok(true);
+        }
+        match self.config_value(name) {
+            None => Ok(false),
+            Some((Value::Flag(b), _)) => Ok(*b),
+            Some((_, source)) => {
+                Err(self.invalid(source, format!("{} should be true or false", name)))
+            }
+        }
+    }
+
+    /// Value of the option which must be given by the command line or config.
+    pub fn required(&self, name: &str) -> Result<&str> {
+        self.value_of(name)?
+            .ok_or_else(|| Error::arg(name, "is required, by the command line or config"))
+    }
+
+    /// Parsed value of the option.
+    pub fn get<T>(&self, name: &str) -> Result<Option<T>>
+    where
+        T: FromStr,
+        T::Err: fmt::Display,
+    {
+        self.value_of(name)?.map(|v| parse_arg(name, v)).transpose()
+    }
+
+    /// Parsed value of the option, `default` if not given.
+    pub fn get_or<T>(&self, name: &str, default: T) -> Result<T>
+    where
+        T: FromStr,
+        T::Err: fmt::Display,
+    {
+        Ok(self.get(name)?.unwrap_or(default))
+    }
+
+    /// Options given by the command line or config file but never read, like
+    /// an option needing another one or a typo in the config, are errors.
+    /// Options of the preset are not checked.
+    pub fn check_unused(&self) -> Result<()> {
+        let used = self.used.borrow();
+        let mut given: Vec<&&str> = self.matches.args.keys().collect();
+        given.sort();
+        for name in given {
+            if !used.contains(*name) {
+                let msg = "has no effect, check the options it depends on";
+                return Err(Error::arg(name, msg));
+            }
+        }
+        for (name, (_, source)) in &self.values {
+            if let (false, Source::File(_)) = (used.contains(name), source) {
+                let msg = format!(
+                    "{} is not an option of {} or has no effect",
+                    name, self.tool
+                );
+                return Err(self.invalid(source, msg));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Check the value of option `name` is in the range, shared by the tools.
+pub fn check_range<T>(name: &str, value: T, min: T, max: T) -> Result<T>
+where
+    T: PartialOrd + fmt::Display,
+{
+    if value < min || value > max {
+        let msg = format!("{} is out of range [{}, {}]", value, min, max);
+        return Err(Error::arg(name, msg));
+    }
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::{App, Arg};
+
+    fn app() -> App<'static, 'static> {
+        App::new("expet")
+            .arg(Arg::with_name("config").long("config").takes_value(true))
+            .arg(Arg::with_name("preset").long("preset").takes_value(true))
+            .arg(Arg::with_name("linker").long("linker").takes_value(true))
+            .arg(
+                Arg::with_name("fq1")
+                    .long("fq1")
+                    .takes_value(true)
+                    .multiple(true),
+            )
+            .arg(Arg::with_name("threads").long("threads").takes_value(true))
+            .arg(Arg::with_name("split_barcode").long("split_barcode"))
+    }
+
+    #[test]
+    fn test_parse_config() {
+        let text = "preset = \"scidlo-pe-msei\"\nthreads = 8\nlinker = \"AC\"\n\
+                    [expet]\nlinker = \"GT\"\nfq1 = [\"a.fq\", \"b.fq\"]\n\
+                    [spcell]\ndist_thresh = 2\n";
+        let source = Source::File("a.toml".to_string());
+        let (preset, options) = parse_config(text, false, "expet", &source).unwrap();
+        assert_eq!(preset.unwrap(), "scidlo-pe-msei");
+        assert_eq!(options["threads"], Value::Text("8".to_string()));
+        assert_eq!(options["linker"], Value::Text("GT".to_string()));
+        assert!(!options.contains_key("dist_thresh"));
+        let yaml = "threads: 8\nexpet:\n  fq1: [a.fq, b.fq]\n  split_barcode: true\n";
+        let (_, options_yaml) = parse_config(yaml, true, "expet", &source).unwrap();
+        assert_eq!(options_yaml["fq1"], options["fq1"]);
+        assert_eq!(options_yaml["split_barcode"], Value::Flag(true));
+        assert!(parse_config("[expet]\nx = [[1]]\n", false, "expet", &source).is_err());
+
+        for (name, _, text) in PRESETS {
+            let source = Source::Preset(name.to_string());
+            for tool in &TOOLS {
+                parse_config(text, false, tool, &source).unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn test_params() {
+        let matches = app().get_matches_from(vec!["expet", "--preset", "scidlo-pe-msei"]);
+        let params = Params::new("expet", &matches).unwrap();
+        assert_eq!(params.value_of("enzyme").unwrap(), Some("T^TA^A"));
+        assert!(params.is_present("split_barcode").unwrap());
+        assert!(params.value_of("split_barcode").is_err());
+        assert_eq!(params.get_or("threads", 1).unwrap(), 1);
+        assert!(params.required("fq1").is_err());
+        assert!(params.check_unused().is_ok());
+
+        let matches = app().get_matches_from(vec!["expet", "--linker", "AC", "--threads", "2"]);
+        let params = Params::new("expet", &matches).unwrap();
+        assert_eq!(params.value_of("linker").unwrap(), Some("AC"));
+        // threads is given but not read
+        assert_eq!(params.check_unused().unwrap_err().exit_code(), 2);
+        assert_eq!(params.get::<u8>("threads").unwrap(), Some(2));
+        assert!(params.check_unused().is_ok());
+
+        assert!(check_range("ratio", 0.5, 0.0, 1.0).is_ok());
+        assert!(check_range("ratio", 1.5, 0.0, 1.0).is_err());
+    }
+}
@@ -0,0 +1,121 @@
+//! Errors of the tools, with the context to locate the problem
+//! and a distinct exit code for each kind.
+
+use std::fmt;
+use std::io;
+use std::str::FromStr;
+
+use log::error;
+
+use crate::{manifest, output};
+
+/// Position of the invalid content in the input file, 1-based.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Pos {
+    Line(u64),
+    Record(u64),
+}
+
+#[derive(Debug)]
+pub enum Error {
+    /// Failed to open, read or write the file.
+    Io { path: String, source: io::Error },
+    /// Invalid content in the input file.
+    Format {
+        path: String,
+        pos: Option<Pos>,
+        msg: String,
+    },
+    /// Invalid value of the command line argument.
+    Arg { name: String, msg: String },
+    /// Inputs can not be used, like an empty barcodes whitelist.
+    Input(String),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    pub fn io(path: &str, source: io::Error) -> Self {
+        Error::Io {
+            path: path.to_string(),
+            source,
+        }
+    }
+
+    pub fn format<S: Into<String>>(path: &str, pos: Option<Pos>, msg: S) -> Self {
+        Error::Format {
+            path: path.to_string(),
+            pos,
+            msg: msg.into(),
+        }
+    }
+
+    pub fn arg<S: Into<String>>(name: &str, msg: S) -> Self {
+        Error::Arg {
+            name: name.to_string(),
+            msg: msg.into(),
+        }
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Arg { .. } => 2,
+            Error::Io { .. } => 3,
+            Error::Format { .. } => 4,
+            Error::Input(_) => 5,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io { path, source } => write!(f, "{}: {}", path, source),
+            Error::Format { path, pos, msg } => match pos {
+                Some(Pos::Line(n)) => write!(f, "{}, line {}: {}", path, n, msg),
+                Some(Pos::Record(n)) => write!(f, "{}, record {}: {}", path, n, msg),
+                None => write!(f, "{}: {}", path, msg),
+            },
+            Error::Arg { name, msg } => write!(f, "Invalid argument --{}: {}", name, msg),
+            Error::Input(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Parse the value of argument `name`.
+pub fn parse_arg<T>(name: &str, value: &str) -> Result<T>
+where
+    T: FromStr,
+    T::Err: fmt::Display,
+{
+    value
+        .parse()
+        .map_err(|e| Error::arg(name, format!("{:?}, {}", value, e)))
+}
+
+/// Log the error, remove the temporary outputs and exit with the code of error.
+pub fn exit_with(e: &Error) -> ! {
+    error!("{}", e);
+    manifest::finish(e.exit_code(), Some(e.to_string()));
+    output::abort(e.exit_code())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_display() {
+        let e = Error::format("a.pairs", Some(Pos::Line(3)), "no barcodes in read ID");
+        assert_eq!(e.to_string(), "a.pairs, line 3: no barcodes in read ID");
+        assert_eq!(e.exit_code(), 4);
+        let e = parse_arg::<usize>("threads", "x").unwrap_err();
+        assert_eq!(e.exit_code(), 2);
+        assert!(e
+            .to_string()
+            .starts_with("Invalid argument --threads: \"x\""));
+        assert_eq!(parse_arg::<f32>("ratio", "0.5").unwrap(), 0.5);
+    }
+}
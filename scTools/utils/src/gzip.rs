@@ -0,0 +1,261 @@
+//! Multi-threaded gzip decompression. BGZF blocks are independent gzip members,
+//! so batches of them are decompressed in parallel. Plain gzip can only be
+//! decompressed by one thread, it is done ahead of the reader like `pigz -d`.
+
+use std::io::{self, prelude::*};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use flate2::read::MultiGzDecoder;
+
+/// Decompressed bytes sent to the reader at a time.
+const CHUNK_SIZE: usize = 1 << 20;
+/// Compressed bytes of the BGZF blocks decompressed by a job.
+const BATCH_SIZE: usize = 1 << 20;
+/// Chunks or jobs ready ahead of the reader, per decompressing thread.
+const READ_AHEAD: usize = 4;
+
+/// Reader of the chunks sent by the decompressing threads.
+pub struct ChunkReader {
+    // Mutex makes the reader Sync, it is never contended
+    rx: Mutex<Receiver<io::Result<Vec<u8>>>>,
+    chunk: Vec<u8>,
+    pos: usize,
+}
+
+impl ChunkReader {
+    fn new(rx: Receiver<io::Result<Vec<u8>>>) -> Self {
+        Self {
+            rx: Mutex::new(rx),
+            chunk: vec![],
+            pos: 0,
+        }
+    }
+}
+
+impl Read for ChunkReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos >= self.chunk.len() {
+            match self.rx.get_mut().unwrap().recv() {
+                Ok(chunk) => {
+                    self.chunk = chunk?;
+                    self.pos = 0;
+                }
+                // all sent
+                Err(_) => return Ok(0),
+            }
+        }
+        let n = buf.len().min(self.chunk.len() - self.pos);
+        buf[..n].copy_from_slice(&self.chunk[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+/// Read `inner` in a background thread, `CHUNK_SIZE` bytes at a time.
+fn spawn_reader<R: Read + Send + 'static>(mut inner: R, tx: SyncSender<io::Result<Vec<u8>>>) {
+    thread::spawn(move || loop {
+        let mut chunk = vec![0; CHUNK_SIZE];
+        let mut n = 0;
+        while n < CHUNK_SIZE {
+            match inner.read(&mut chunk[n..]) {
+                Ok(0) => break,
+                Ok(m) => n += m,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    let _ = tx.send(Err(e));
+                    return;
+                }
+            }
+        }
+        chunk.truncate(n);
+        // stop if reached the end, or the reader is dropped
+        if n == 0 || tx.send(Ok(chunk)).is_err() {
+            return;
+        }
+    });
+}
+
+/// Decompress gzip (single or multi-member) in a background thread.
+pub fn read_ahead<R: Read + Send + 'static>(inner: R) -> ChunkReader {
+    let (tx, rx) = mpsc::sync_channel(READ_AHEAD);
+    spawn_reader(MultiGzDecoder::new(inner), tx);
+    ChunkReader::new(rx)
+}
+
+/// Check the header of gzip member is of BGZF, with the "BC" extra subfield.
+pub fn is_bgzf(head: &[u8]) -> bool {
+    head.len() >= 16
+        && head[..4] == [0x1f, 0x8b, 0x08, 0x04]
+        && head[12..14] == [b'B', b'C']
+        && head[14..16] == [2, 0]
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Read one BGZF block and append it to `buf`, false at the end of input.
+fn read_block<R: Read>(inner: &mut R, buf: &mut Vec<u8>) -> io::Result<bool> {
+    let start = buf.len();
+    let mut header = [0; 12];
+    let mut n = 0;
+    while n < header.len() {
+        match inner.read(&mut header[n..]) {
+            Ok(0) if n == 0 => return Ok(false),
+            Ok(0) => return Err(invalid("truncated BGZF block")),
+            Ok(m) => n += m,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    if header[..4] != [0x1f, 0x8b, 0x08, 0x04] {
+        return Err(invalid("not a BGZF block"));
+    }
+    let xlen = u16::from_le_bytes([header[10], header[11]]) as usize;
+    buf.extend_from_slice(&header);
+    buf.resize(start + 12 + xlen, 0);
+    inner.read_exact(&mut buf[start + 12..])?;
+    // find the block size in the extra subfields
+    let mut bsize = None;
+    let mut extra = &buf[start + 12..];
+    while extra.len() >= 4 {
+        let slen = u16::from_le_bytes([extra[2], extra[3]]) as usize;
+        if extra[..2] == [b'B', b'C'] && slen == 2 && extra.len() >= 6 {
+            bsize = Some(u16::from_le_bytes([extra[4], extra[5]]) as usize + 1);
+        }
+        extra = &extra[(4 + slen).min(extra.len())..];
+    }
+    let bsize = bsize.ok_or_else(|| invalid("BGZF block without size"))?;
+    if bsize < 12 + xlen {
+        return Err(invalid("invalid BGZF block size"));
+    }
+    buf.resize(start + bsize, 0);
+    inner
+        .read_exact(&mut buf[start + 12 + xlen..])
+        .map_err(|_| invalid("truncated BGZF block"))?;
+    Ok(true)
+}
+
+type Job = (Vec<u8>, SyncSender<io::Result<Vec<u8>>>);
+
+/// Decompress BGZF with `threads` threads. Batches of blocks are dispatched to the
+/// workers, and the results are collected in the order of the batches.
+pub fn read_bgzf<R: Read + Send + 'static>(mut inner: R, threads: usize) -> ChunkReader {
+    let threads = threads.max(1);
+    let (job_tx, job_rx) = mpsc::sync_channel::<Job>(threads);
+    let job_rx = Arc::new(Mutex::new(job_rx));
+    for _ in 0..threads {
+        let job_rx = Arc::clone(&job_rx);
+        thread::spawn(move || loop {
+            let job = job_rx.lock().unwrap().recv();
+            let (batch, res_tx) = match job {
+                Ok(job) => job,
+                Err(_) => return,
+            };
+            let mut data = Vec::with_capacity(batch.len() * 4);
+            let res = MultiGzDecoder::new(&batch[..])
+                .read_to_end(&mut data)
+                .map(|_| data);
+            let _ = res_tx.send(res);
+        });
+    }
+
+    // receivers of the results, in the order of the batches
+    let (order_tx, order_rx) = mpsc::sync_channel(threads * READ_AHEAD);
+    thread::spawn(move || loop {
+        let mut batch = Vec::with_capacity(BATCH_SIZE + (1 << 16));
+        let mut res = Ok(true);
+        while batch.len() < BATCH_SIZE {
+            res = read_block(&mut inner, &mut batch);
+            if !matches!(res, Ok(true)) {
+                break;
+            }
+        }
+        if !batch.is_empty() {
+            let (res_tx, res_rx) = mpsc::sync_channel(1);
+            if order_tx.send(Ok(res_rx)).is_err() || job_tx.send((batch, res_tx)).is_err() {
+                return;
+            }
+        }
+        match res {
+            Ok(true) => {}
+            Ok(false) => return,
+            Err(e) => {
+                let _ = order_tx.send(Err(e));
+                return;
+            }
+        }
+    });
+
+    let (tx, rx) = mpsc::sync_channel(READ_AHEAD);
+    thread::spawn(move || {
+        for res_rx in order_rx {
+            let res = match res_rx {
+                Ok(res_rx) => res_rx
+                    .recv()
+                    .unwrap_or_else(|_| Err(invalid("BGZF worker stopped"))),
+                Err(e) => Err(e),
+            };
+            let stop = res.is_err();
+            if tx.send(res).is_err() || stop {
+                return;
+            }
+        }
+    });
+    ChunkReader::new(rx)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use flate2::{Compression, GzBuilder};
+
+    /// BGZF block of `data`, the header has the extra subfield of block size.
+    fn bgzf_block(data: &[u8]) -> Vec<u8> {
+        let mut e = GzBuilder::new()
+            .extra(vec![b'B', b'C', 2, 0, 0, 0])
+            .write(vec![], Compression::fast());
+        e.write_all(data).unwrap();
+        let mut block = e.finish().unwrap();
+        let bsize = (block.len() - 1) as u16;
+        block[16..18].copy_from_slice(&bsize.to_le_bytes());
+        block
+    }
+
+    #[test]
+    fn test_read_bgzf() {
+        let lines: Vec<String> = (0..200000).map(|i| format!("line {}\n", i)).collect();
+        let expected = lines.concat().into_bytes();
+        let mut bgzf = vec![];
+        for part in expected.chunks(60000) {
+            bgzf.extend(bgzf_block(part));
+        }
+        bgzf.extend(bgzf_block(b""));
+        assert!(is_bgzf(&bgzf));
+
+        let mut data = vec![];
+        read_bgzf(io::Cursor::new(bgzf.clone()), 4)
+            .read_to_end(&mut data)
+            .unwrap();
+        assert_eq!(data, expected);
+
+        let mut data = vec![];
+        read_ahead(io::Cursor::new(bgzf.clone()))
+            .read_to_end(&mut data)
+            .unwrap();
+        assert_eq!(data, expected);
+
+        // plain gzip member in the middle
+        let mut e = GzEncoder::new(vec![], Compression::fast());
+        e.write_all(b"x").unwrap();
+        let plain = e.finish().unwrap();
+        assert!(!is_bgzf(&plain));
+        let mut bad = bgzf_block(b"a");
+        bad.extend(plain);
+        let res = read_bgzf(io::Cursor::new(bad), 2).read_to_end(&mut vec![]);
+        assert!(res.is_err());
+    }
+}
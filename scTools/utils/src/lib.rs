@@ -3,50 +3,178 @@ use std::fs::File;
 use std::hash::Hash;
 use std::io::{self, prelude::*};
 use std::ops::AddAssign;
-use std::path::Path;
 
+extern crate bzip2;
 extern crate flate2;
-use flate2::read::GzDecoder;
+extern crate xz2;
+extern crate zstd;
+use bzip2::read::MultiBzDecoder;
+use xz2::read::XzDecoder;
 
-pub fn open_file(path: &str) -> Box<dyn Read + Send + Sync> {
-    let f: Box<dyn Read + Send + Sync> = if path.ends_with(".gz") {
-        Box::new(GzDecoder::new(File::open(path).unwrap()))
-    } else {
-        Box::new(File::open(path).unwrap())
-    };
-    f
+pub mod config;
+pub mod error;
+pub mod gzip;
+pub mod manifest;
+pub mod output;
+
+use error::Error;
+
+/// Compression format of the input, detected from the magic bytes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Compression {
+    Plain,
+    Gzip,
+    /// Blocked gzip of htslib, a series of gzip members with the block sizes.
+    Bgzf,
+    Zstd,
+    Bzip2,
+    Xz,
 }
 
-pub fn open_file_buffered(path: &str) -> io::BufReader<Box<dyn Read + Send + Sync>> {
-    let f = open_file(path);
-    let buf_read = io::BufReader::new(f);
-    buf_read
+const MAGIC_LEN: usize = 16;
+
+impl Compression {
+    pub fn detect(head: &[u8]) -> Self {
+        if gzip::is_bgzf(head) {
+            Compression::Bgzf
+        } else if head.starts_with(&[0x1f, 0x8b]) {
+            Compression::Gzip
+        } else if head.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Compression::Zstd
+        } else if head.starts_with(b"BZh") {
+            Compression::Bzip2
+        } else if head.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+            Compression::Xz
+        } else {
+            Compression::Plain
+        }
+    }
 }
 
-pub fn read_lines<P>(filename: P) -> io::Result<io::Lines<io::BufReader<File>>>
-where
-    P: AsRef<Path>,
-{
-    let file = File::open(filename)?;
-    Ok(io::BufReader::new(file).lines())
+/// Open file for reading, "-" for stdin. Compressed input is detected from the
+/// magic bytes and decompressed, concatenated streams are read to the end.
+pub fn open_file(path: &str) -> error::Result<Box<dyn Read + Send + Sync>> {
+    open_file_threads(path, 1)
+}
+
+/// Like `open_file`, BGZF input is decompressed by `threads` threads.
+/// Gzip is always decompressed in a background thread ahead of the reading.
+pub fn open_file_threads(path: &str, threads: usize) -> error::Result<Box<dyn Read + Send + Sync>> {
+    let mut f: Box<dyn Read + Send + Sync> = if path == "-" {
+        Box::new(io::stdin())
+    } else {
+        Box::new(File::open(path).map_err(|e| Error::io(path, e))?)
+    };
+    let mut head = vec![0; MAGIC_LEN];
+    let mut n = 0;
+    while n < MAGIC_LEN {
+        match f.read(&mut head[n..]) {
+            Ok(0) => break,
+            Ok(m) => n += m,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(Error::io(path, e)),
+        }
+    }
+    head.truncate(n);
+    let compression = Compression::detect(&head);
+    // put the magic bytes back before the rest of the stream
+    let f = io::Cursor::new(head).chain(f);
+    let f: Box<dyn Read + Send + Sync> = match compression {
+        Compression::Plain => Box::new(f),
+        Compression::Bgzf if threads > 1 => Box::new(gzip::read_bgzf(f, threads)),
+        Compression::Gzip | Compression::Bgzf => Box::new(gzip::read_ahead(f)),
+        Compression::Zstd => Box::new(zstd::Decoder::new(f).map_err(|e| Error::io(path, e))?),
+        Compression::Bzip2 => Box::new(MultiBzDecoder::new(f)),
+        Compression::Xz => Box::new(XzDecoder::new_multi_decoder(f)),
+    };
+    Ok(f)
+}
+
+pub fn open_file_buffered(path: &str) -> error::Result<io::BufReader<Box<dyn Read + Send + Sync>>> {
+    let f = open_file(path)?;
+    Ok(io::BufReader::new(f))
 }
 
 pub fn add_hashmap<T, R>(m1: HashMap<T, R>, m2: HashMap<T, R>) -> HashMap<T, R>
 where
     T: Clone + Eq + Hash,
-    R: Clone + AddAssign + Copy,
+    R: Clone + AddAssign + Default,
 {
     let mut m = m1.clone();
     for (k, v) in m2 {
-        *m.entry(k).or_insert(v) += v;
+        *m.entry(k).or_default() += v;
     }
-    return m;
+    m
 }
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+
     #[test]
     fn it_works() {
         assert_eq!(2 + 2, 4);
     }
+
+    #[test]
+    fn test_add_hashmap() {
+        let m1: HashMap<&str, u64> = [("a", 1), ("b", 2)].iter().cloned().collect();
+        let m2: HashMap<&str, u64> = [("b", 3), ("c", 4)].iter().cloned().collect();
+        let m = add_hashmap(m1, m2);
+        assert_eq!(m["a"], 1);
+        assert_eq!(m["b"], 5);
+        assert_eq!(m["c"], 4);
+    }
+
+    #[test]
+    fn test_open_file() {
+        let dir = std::env::temp_dir().join(format!("utils_open_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (a, b) = (b"@r1\nACGT\n+\nIIII\n", b"@r2\nTTGA\n+\nIIII\n");
+        let expected = [&a[..], &b[..]].concat();
+
+        // every format has two concatenated streams, like the BGZF blocks
+        let mut files: Vec<(&str, Vec<u8>)> = vec![("plain.fq", expected.clone())];
+        let gz = |d: &[u8]| {
+            let mut e = flate2::write::GzEncoder::new(vec![], flate2::Compression::fast());
+            e.write_all(d).unwrap();
+            e.finish().unwrap()
+        };
+        files.push(("a.fq.gz", [gz(a), gz(b)].concat()));
+        let zst = |d: &[u8]| zstd::encode_all(d, 1).unwrap();
+        files.push(("a.fq.zst", [zst(a), zst(b)].concat()));
+        let bz2 = |d: &[u8]| {
+            let mut e = bzip2::write::BzEncoder::new(vec![], bzip2::Compression::fast());
+            e.write_all(d).unwrap();
+            e.finish().unwrap()
+        };
+        files.push(("a.fq.bz2", [bz2(a), bz2(b)].concat()));
+        let xz = |d: &[u8]| {
+            let mut e = xz2::write::XzEncoder::new(vec![], 1);
+            e.write_all(d).unwrap();
+            e.finish().unwrap()
+        };
+        // without the name suffix, format is detected from the content
+        files.push(("a.fq", [xz(a), xz(b)].concat()));
+
+        for (name, data) in files {
+            let path = dir.join(name);
+            std::fs::write(&path, data).unwrap();
+            let mut content = vec![];
+            open_file(path.to_str().unwrap())
+                .unwrap()
+                .read_to_end(&mut content)
+                .unwrap();
+            assert_eq!(content, expected, "{}", name);
+        }
+        std::fs::write(dir.join("short"), b"A").unwrap();
+        let mut content = String::new();
+        open_file(dir.join("short").to_str().unwrap())
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        assert_eq!(content, "A");
+        assert_eq!(open_file("no_such_file").err().unwrap().exit_code(), 3);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
 }
@@ -0,0 +1,224 @@
+//! Manifest of a run, written to `{prefix}.run.json`: version of the tool, resolved
+//! parameters, checksums of the inputs, times and the exit status, so that the
+//! results are auditable and reproducible.
+
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, prelude::*};
+use std::sync::Mutex;
+use std::thread::{self, JoinHandle};
+
+use log::warn;
+use serde::Serialize;
+use serde_json::Value;
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+
+use crate::error::Result;
+use crate::output;
+
+pub const GIT_HASH: &str = env!("GIT_HASH");
+
+#[derive(Debug, Serialize)]
+pub struct InputFile {
+    pub path: String,
+    /// `None` for stdin.
+    pub size: Option<u64>,
+    pub md5: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Manifest {
+    pub tool: String,
+    pub version: String,
+    pub git_hash: String,
+    pub command: Vec<String>,
+    pub params: BTreeMap<String, Value>,
+    pub inputs: Vec<InputFile>,
+    pub threads: usize,
+    pub start_time: String,
+    pub end_time: Option<String>,
+    pub exit_status: Option<i32>,
+    pub error: Option<String>,
+}
+
+/// Manifest of the current run, with its path and the threads computing the checksums.
+struct Running {
+    path: String,
+    manifest: Manifest,
+    checksums: Vec<JoinHandle<io::Result<String>>>,
+}
+
+static RUNNING: Mutex<Option<Running>> = Mutex::new(None);
+
+fn now() -> String {
+    OffsetDateTime::now_utc()
+        .format(&Rfc3339)
+        .unwrap_or_default()
+}
+
+fn md5_of(path: &str) -> io::Result<String> {
+    let mut f = File::open(path)?;
+    let mut ctx = md5::Context::new();
+    let mut buf = vec![0; 1 << 20];
+    loop {
+        let n = f.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        ctx.consume(&buf[..n]);
+    }
+    Ok(format!("{:x}", ctx.compute()))
+}
+
+/// The manifest at `path` records a non-zero exit status.
+fn is_failed(path: &str) -> bool {
+    fs::read_to_string(path)
+        .ok()
+        .and_then(|s| serde_json::from_str::<Value>(&s).ok())
+        .and_then(|v| v["exit_status"].as_i64())
+        .is_some_and(|status| status != 0)
+}
+
+impl Manifest {
+    /// Start a manifest at the start time of run.
+    pub fn new(tool: &str, version: &str) -> Self {
+        Self {
+            tool: tool.to_string(),
+            version: version.to_string(),
+            git_hash: GIT_HASH.to_string(),
+            command: std::env::args().collect(),
+            params: BTreeMap::new(),
+            inputs: vec![],
+            threads: 1,
+            start_time: now(),
+            end_time: None,
+            exit_status: None,
+            error: None,
+        }
+    }
+
+    pub fn param<T: Serialize>(&mut self, name: &str, value: T) {
+        // through the text, f32 is written as 0.8 instead of 0.800000011920929
+        let value = serde_json::to_string(&value)
+            .and_then(|v| serde_json::from_str(&v))
+            .unwrap_or(Value::Null);
+        self.params.insert(name.to_string(), value);
+    }
+
+    pub fn input(&mut self, path: &str) {
+        let size = if path == "-" {
+            None
+        } else {
+            fs::metadata(path).ok().map(|m| m.len())
+        };
+        self.inputs.push(InputFile {
+            path: path.to_string(),
+            size,
+            md5: None,
+        });
+    }
+}
+
+/// Register the manifest of the run, written to `path` when it finishes.
+/// Checksums of the inputs are computed in background.
+/// Existing manifest of a successful run is not overwritten unless `force`,
+/// the manifest of a failed run is, so that the run can be repeated.
+pub fn start(path: &str, force: bool, manifest: Manifest) -> Result<()> {
+    output::check_exists(path, force || is_failed(path))?;
+    let checksums = manifest
+        .inputs
+        .iter()
+        .filter(|i| i.size.is_some())
+        .map(|i| {
+            let p = i.path.clone();
+            thread::spawn(move || md5_of(&p))
+        })
+        .collect();
+    *RUNNING.lock().unwrap() = Some(Running {
+        path: path.to_string(),
+        manifest,
+        checksums,
+    });
+    Ok(())
+}
+
+/// Write the manifest with the exit status, only the first call takes effect.
+/// Checksums are waited only for the successful run.
+pub fn finish(exit_status: i32, error: Option<String>) {
+    let running = match RUNNING.lock() {
+        Ok(mut r) => r.take(),
+        Err(_) => None,
+    };
+    let Running {
+        path,
+        mut manifest,
+        checksums,
+    } = match running {
+        Some(r) => r,
+        None => return,
+    };
+    if exit_status == 0 {
+        let inputs = manifest.inputs.iter_mut().filter(|i| i.size.is_some());
+        for (input, handle) in inputs.zip(checksums) {
+            match handle.join().unwrap() {
+                Ok(md5) => input.md5 = Some(md5),
+                Err(e) => warn!("Failed to compute the checksum of {}: {}", input.path, e),
+            }
+        }
+    }
+    manifest.end_time = Some(now());
+    manifest.exit_status = Some(exit_status);
+    manifest.error = error;
+    let json = serde_json::to_string_pretty(&manifest).unwrap();
+    if let Err(e) = fs::write(&path, json + "\n") {
+        warn!("Failed to write the manifest {}: {}", path, e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_manifest() {
+        let dir = std::env::temp_dir().join(format!("utils_manifest_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("a.txt");
+        fs::write(&input, "abc").unwrap();
+        let path = dir.join("a.run.json");
+        let path = path.to_str().unwrap();
+
+        let mut m = Manifest::new("tool", "0.1.0");
+        m.param("min_len", 10);
+        m.param("adapter", None::<String>);
+        m.param("ratio", 0.8f32);
+        m.input(input.to_str().unwrap());
+        m.input("-");
+        start(path, false, m).unwrap();
+        finish(0, None);
+        finish(1, None);
+
+        let v: Value = serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap();
+        assert_eq!(v["params"]["min_len"], 10);
+        assert_eq!(v["params"]["adapter"], Value::Null);
+        assert_eq!(v["params"]["ratio"].to_string(), "0.8");
+        assert_eq!(v["inputs"][0]["size"], 3);
+        assert_eq!(v["inputs"][0]["md5"], "900150983cd24fb0d6963f7d28e17f72");
+        assert_eq!(v["inputs"][1]["md5"], Value::Null);
+        assert_eq!(v["exit_status"], 0);
+        let m = Manifest::new("tool", "0.1.0");
+        assert!(start(path, false, m).is_err());
+
+        // the manifest of a failed run is overwritten
+        let m = Manifest::new("tool", "0.1.0");
+        start(path, true, m).unwrap();
+        finish(3, Some("Input error".to_string()));
+        let m = Manifest::new("tool", "0.1.0");
+        start(path, false, m).unwrap();
+        finish(0, None);
+        let v: Value = serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap();
+        assert_eq!(v["exit_status"], 0);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,143 @@
+//! Outputs are written to temporary files and renamed to the final paths only
+//! when the run succeeds, so a crashed or interrupted run leaves no truncated
+//! outputs which look valid to the pipeline.
+
+use std::fs::{self, File};
+use std::path::Path;
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::thread::JoinHandle;
+
+use log::warn;
+
+use crate::error::{Error, Result};
+use crate::manifest;
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+/// Temporary and final paths of the outputs not committed.
+static PENDING: Mutex<Vec<(String, String)>> = Mutex::new(Vec::new());
+
+/// Exit code of the runs interrupted by signal.
+pub const EXIT_INTERRUPTED: i32 = 130;
+/// Exit code of the runs stopped by a panicked worker, same as a panic of Rust.
+pub const EXIT_PANIC: i32 = 101;
+
+/// Handle SIGINT and SIGTERM. The first signal only marks the run interrupted,
+/// writer loops should check `is_interrupted` and stop, then `commit` removes the
+/// temporary files. The second signal removes them and exits at once.
+pub fn handle_signals() {
+    ctrlc::set_handler(|| {
+        if INTERRUPTED.swap(true, Ordering::SeqCst) {
+            abort_interrupted();
+        }
+        warn!("Interrupted, stop writing outputs.");
+    })
+    .unwrap();
+}
+
+pub fn is_interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+fn tmp_path(path: &str) -> String {
+    format!("{}.tmp", path)
+}
+
+/// Existing output `path` is not overwritten unless `force`.
+pub fn check_exists(path: &str, force: bool) -> Result<()> {
+    if !force && Path::new(path).exists() {
+        return Err(Error::Input(format!(
+            "Output {} exists, use --force to overwrite it.",
+            path
+        )));
+    }
+    Ok(())
+}
+
+/// Create the temporary file of output `path`.
+/// Existing output is not overwritten unless `force`.
+pub fn create(path: &str, force: bool) -> Result<File> {
+    check_exists(path, force)?;
+    let tmp = tmp_path(path);
+    let f = File::create(&tmp).map_err(|e| Error::io(&tmp, e))?;
+    PENDING.lock().unwrap().push((tmp, path.to_string()));
+    Ok(f)
+}
+
+/// Wait for all the worker threads and return the first error of them.
+/// If a worker panicked, remove the temporary files and exit.
+pub fn join_workers(handles: Vec<JoinHandle<Result<()>>>) -> Result<()> {
+    let mut res = Ok(());
+    for handle in handles {
+        match handle.join() {
+            Ok(r) => res = res.and(r),
+            Err(_) => abort(EXIT_PANIC),
+        }
+    }
+    res
+}
+
+/// Rename the temporary files to the outputs, only after all the inputs are
+/// processed and `join_workers` succeeded. If the run was interrupted, remove
+/// them and exit instead.
+pub fn commit() -> Result<()> {
+    exit_if_interrupted();
+    for (tmp, path) in PENDING.lock().unwrap().drain(..) {
+        fs::rename(&tmp, &path).map_err(|e| Error::io(&path, e))?;
+    }
+    Ok(())
+}
+
+/// Remove the temporary files and exit, if the run was interrupted.
+pub fn exit_if_interrupted() {
+    if is_interrupted() {
+        warn!("Run interrupted, temporary outputs are removed.");
+        abort_interrupted();
+    }
+}
+
+fn abort_interrupted() -> ! {
+    manifest::finish(EXIT_INTERRUPTED, Some("Interrupted".to_string()));
+    abort(EXIT_INTERRUPTED)
+}
+
+/// Remove the temporary files and exit with `code`.
+pub fn abort(code: i32) -> ! {
+    manifest::finish(code, None);
+    remove_pending();
+    process::exit(code)
+}
+
+fn remove_pending() {
+    // the lock may be held by the thread interrupted
+    if let Ok(mut pending) = PENDING.try_lock() {
+        for (tmp, _) in pending.drain(..) {
+            let _ = fs::remove_file(tmp);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn test_create_commit() {
+        let dir = std::env::temp_dir().join(format!("utils_output_{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("a.txt");
+        let path = path.to_str().unwrap();
+        let mut f = create(path, false).unwrap();
+        writeln!(f, "x").unwrap();
+        assert!(!Path::new(path).exists());
+        assert!(Path::new(&tmp_path(path)).exists());
+        commit().unwrap();
+        assert!(Path::new(path).exists());
+        assert!(create(path, false).is_err());
+        assert!(!Path::new(&tmp_path(path)).exists());
+        assert_eq!(fs::read_to_string(path).unwrap(), "x\n");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}